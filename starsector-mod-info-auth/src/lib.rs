@@ -1,4 +1,9 @@
-use starsector_mod_info_shared::{rate_limit, user::User};
+use serde_json::Value;
+use starsector_mod_info_shared::{
+  message::{Message, UserCreated},
+  publisher, rate_limit,
+  user::User,
+};
 use worker::*;
 
 mod utils;
@@ -31,7 +36,13 @@ pub async fn main(req: Request, env: Env, _ctx: worker::Context) -> Result<Respo
   router
     .get_async("/generate", |req, ctx| async move {
       rate_limit!(&req, 1, "generate");
-      User::new(ctx)?.init().await
+      let generated: Value = User::new(&ctx)?.init().await?.json().await?;
+      if let Some(user_id) = generated["id"].as_str() {
+        let created = UserCreated::new(user_id.to_owned());
+        publisher::notify(&ctx.env, Message::UserCreated(created)).await;
+      }
+
+      Response::ok(generated.to_string())
     })
     .get("/worker_version", |_, ctx| {
       let version = ctx.var("WORKERS_RS_VERSION")?.to_string();
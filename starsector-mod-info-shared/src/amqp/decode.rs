@@ -0,0 +1,129 @@
+use std::{fmt, string::FromUtf8Error};
+
+use serde::{de::DeserializeOwned, Deserialize};
+
+use super::{PayloadEncoding, Properties};
+
+/// A message as delivered by the broker, the inverse of [`HTTPAmqp`](super::HTTPAmqp).
+#[derive(Deserialize, Debug)]
+pub struct Message {
+  #[serde(default)]
+  pub properties: Properties,
+  #[serde(default)]
+  pub routing_key: String,
+  payload: String,
+  #[serde(default)]
+  payload_encoding: PayloadEncoding,
+}
+
+impl Message {
+  /// A message carrying `payload` as is, with no properties.
+  pub fn plain(payload: &str) -> Self {
+    Message {
+      properties: Properties::default(),
+      routing_key: String::new(),
+      payload: payload.to_owned(),
+      payload_encoding: PayloadEncoding::String,
+    }
+  }
+
+  /// The payload, decoded from its `payload_encoding`.
+  pub fn payload(&self) -> Result<String, DecodeError> {
+    match self.payload_encoding {
+      PayloadEncoding::String => Ok(self.payload.clone()),
+      PayloadEncoding::Base64 => {
+        let bytes = base64::decode(&self.payload).map_err(DecodeError::Base64)?;
+        String::from_utf8(bytes).map_err(DecodeError::Utf8)
+      }
+    }
+  }
+
+  /// The payload, decoded and then parsed as JSON.
+  pub fn json<T: DeserializeOwned>(&self) -> Result<T, DecodeError> {
+    serde_json::from_str(&self.payload()?).map_err(DecodeError::Json)
+  }
+}
+
+#[derive(Debug)]
+pub enum DecodeError {
+  Base64(base64::DecodeError),
+  Utf8(FromUtf8Error),
+  Json(serde_json::Error),
+}
+
+impl fmt::Display for DecodeError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      DecodeError::Base64(err) => write!(f, "Payload is not valid base64: {}", err),
+      DecodeError::Utf8(err) => write!(f, "Payload is not valid UTF-8: {}", err),
+      DecodeError::Json(err) => write!(f, "Payload is not valid JSON: {}", err),
+    }
+  }
+}
+
+impl std::error::Error for DecodeError {}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Delivery {
+  Batch(Vec<Message>),
+  Single(Box<Message>),
+}
+
+/// Splits a webhook body into the messages it delivers, either a single message or a batch.
+///
+/// A body that is not a broker message is taken as a single plain payload, as sent by the
+/// Cloudflare Queue, by dead letter replays and by webhooks that forward only the payload.
+pub fn decode(body: &str) -> Vec<Message> {
+  match serde_json::from_str::<Delivery>(body) {
+    Ok(Delivery::Batch(messages)) => messages,
+    Ok(Delivery::Single(message)) => vec![*message],
+    Err(_) => vec![Message::plain(body)],
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use serde_json::Value;
+
+  use super::decode;
+  use crate::amqp::{DeliveryMode, HTTPAmqp, Properties, SCHEMA_VERSION_HEADER};
+
+  #[test]
+  fn test_decode() {
+    let published: String = HTTPAmqp::from_json("write", r#"{"mods":[]}"#)
+      .with_properties(
+        Properties::default()
+          .delivery_mode(DeliveryMode::Persistent)
+          .header(SCHEMA_VERSION_HEADER, 2),
+      )
+      .try_into()
+      .unwrap();
+
+    let messages = decode(&published);
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].routing_key, "write");
+    assert_eq!(messages[0].payload().unwrap(), r#"{"mods":[]}"#);
+    assert_eq!(messages[0].properties.schema_version(), Some(2));
+
+    let batch = format!(
+      r#"[{}, {{"payload": "[1]", "payload_encoding": "string"}}]"#,
+      published
+    );
+    let messages = decode(&batch);
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[1].json::<Value>().unwrap(), Value::from(vec![1]));
+
+    let bare = r#"[{"mods":[]}]"#;
+    let messages = decode(bare);
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].payload().unwrap(), bare);
+    assert_eq!(messages[0].properties, Properties::default());
+
+    assert!(
+      decode(r#"{"payload": "!", "payload_encoding": "base64"}"#)[0]
+        .payload()
+        .is_err()
+    );
+  }
+}
@@ -1,23 +1,52 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
+mod decode;
+mod properties;
+
+pub use decode::{decode, DecodeError, Message};
+pub use properties::{DeliveryMode, Properties, SCHEMA_VERSION_HEADER};
+
+/// A message for the publish endpoint of the RabbitMQ HTTP API.
 #[derive(Serialize)]
 pub struct HTTPAmqp {
-  properties: Stub,
+  properties: Properties,
   routing_key: String,
   payload: String,
-  payload_encoding: String,
+  payload_encoding: PayloadEncoding,
+}
+
+/// How a message's payload is encoded. The RabbitMQ HTTP API calls plain text `string`.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadEncoding {
+  #[default]
+  #[serde(alias = "plain")]
+  String,
+  Base64,
 }
 
 impl HTTPAmqp {
   pub fn new(routing_key: &str, payload: impl Serialize) -> Result<Self, serde_json::Error> {
-    let encoded = base64::encode(serde_json::to_string(&payload)?);
+    Ok(Self::from_json(
+      routing_key,
+      &serde_json::to_string(&payload)?,
+    ))
+  }
 
-    Ok(HTTPAmqp {
-      properties: Stub {},
+  /// Like [`HTTPAmqp::new`], for a payload that is already serialized.
+  pub fn from_json(routing_key: &str, json: &str) -> Self {
+    HTTPAmqp {
+      properties: Properties::default(),
       routing_key: routing_key.to_owned(),
-      payload: encoded,
-      payload_encoding: "base64".to_owned(),
-    })
+      payload: base64::encode(json),
+      payload_encoding: PayloadEncoding::Base64,
+    }
+  }
+
+  /// Replaces the message's properties.
+  pub fn with_properties(mut self, properties: Properties) -> Self {
+    self.properties = properties;
+    self
   }
 }
 
@@ -29,5 +58,32 @@ impl TryFrom<HTTPAmqp> for String {
   }
 }
 
-#[derive(Serialize)]
-struct Stub {}
+#[cfg(test)]
+mod test {
+  use serde_json::{json, Value};
+
+  use super::{DeliveryMode, HTTPAmqp, Properties};
+
+  #[test]
+  fn test_properties() {
+    let message = HTTPAmqp::from_json("write", "[]").with_properties(
+      Properties::default()
+        .delivery_mode(DeliveryMode::Persistent)
+        .message_id("abc")
+        .timestamp(1000)
+        .header("x-schema-version", 2),
+    );
+    let message: String = message.try_into().unwrap();
+    let message: Value = serde_json::from_str(&message).unwrap();
+
+    assert_eq!(
+      message["properties"],
+      json!({
+        "delivery_mode": 2,
+        "message_id": "abc",
+        "timestamp": 1000,
+        "headers": { "x-schema-version": 2 },
+      })
+    );
+  }
+}
@@ -0,0 +1,138 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+
+/// Header recording which version of the payload's schema a message was published with.
+pub const SCHEMA_VERSION_HEADER: &str = "x-schema-version";
+
+/// AMQP basic properties, as accepted by the RabbitMQ HTTP API. Unset properties are omitted.
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct Properties {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub content_type: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub content_encoding: Option<String>,
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub headers: BTreeMap<String, Value>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub delivery_mode: Option<DeliveryMode>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub priority: Option<u8>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub correlation_id: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub reply_to: Option<String>,
+  /// Per-message TTL in milliseconds. AMQP carries it as a string.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub expiration: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub message_id: Option<String>,
+  /// Seconds since the Unix epoch.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub timestamp: Option<i64>,
+  #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+  pub kind: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub user_id: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub app_id: Option<String>,
+}
+
+impl Properties {
+  /// The payload's schema version, from the [`SCHEMA_VERSION_HEADER`] header.
+  pub fn schema_version(&self) -> Option<u64> {
+    self.headers.get(SCHEMA_VERSION_HEADER)?.as_u64()
+  }
+
+  pub fn content_type(mut self, content_type: impl Into<String>) -> Self {
+    self.content_type = Some(content_type.into());
+    self
+  }
+
+  pub fn content_encoding(mut self, content_encoding: impl Into<String>) -> Self {
+    self.content_encoding = Some(content_encoding.into());
+    self
+  }
+
+  pub fn header(mut self, name: impl Into<String>, value: impl Into<Value>) -> Self {
+    self.headers.insert(name.into(), value.into());
+    self
+  }
+
+  pub fn delivery_mode(mut self, delivery_mode: DeliveryMode) -> Self {
+    self.delivery_mode = Some(delivery_mode);
+    self
+  }
+
+  pub fn priority(mut self, priority: u8) -> Self {
+    self.priority = Some(priority);
+    self
+  }
+
+  pub fn correlation_id(mut self, correlation_id: impl Into<String>) -> Self {
+    self.correlation_id = Some(correlation_id.into());
+    self
+  }
+
+  pub fn reply_to(mut self, reply_to: impl Into<String>) -> Self {
+    self.reply_to = Some(reply_to.into());
+    self
+  }
+
+  pub fn expiration(mut self, millis: u64) -> Self {
+    self.expiration = Some(millis.to_string());
+    self
+  }
+
+  pub fn message_id(mut self, message_id: impl Into<String>) -> Self {
+    self.message_id = Some(message_id.into());
+    self
+  }
+
+  pub fn timestamp(mut self, timestamp: i64) -> Self {
+    self.timestamp = Some(timestamp);
+    self
+  }
+
+  pub fn kind(mut self, kind: impl Into<String>) -> Self {
+    self.kind = Some(kind.into());
+    self
+  }
+
+  pub fn user_id(mut self, user_id: impl Into<String>) -> Self {
+    self.user_id = Some(user_id.into());
+    self
+  }
+
+  pub fn app_id(mut self, app_id: impl Into<String>) -> Self {
+    self.app_id = Some(app_id.into());
+    self
+  }
+}
+
+/// Whether the broker keeps a message on disk, so it survives a broker restart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeliveryMode {
+  Transient = 1,
+  Persistent = 2,
+}
+
+impl Serialize for DeliveryMode {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u8(*self as u8)
+  }
+}
+
+impl<'de> Deserialize<'de> for DeliveryMode {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    match u8::deserialize(deserializer)? {
+      1 => Ok(DeliveryMode::Transient),
+      2 => Ok(DeliveryMode::Persistent),
+      mode => Err(serde::de::Error::custom(format!(
+        "invalid delivery mode: {}",
+        mode
+      ))),
+    }
+  }
+}
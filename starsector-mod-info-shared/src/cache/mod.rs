@@ -5,3 +5,49 @@ pub struct Rate {
   pub count: u32,
   pub timeout: bool,
 }
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// Builds a strong ETag from the given parts, such as the R2 etags of every object a response
+/// was built from. The result only depends on the parts and their order.
+pub fn etag<'a>(parts: impl IntoIterator<Item = &'a str>) -> String {
+  let hash = parts.into_iter().fold(FNV_OFFSET, |hash, part| {
+    // Terminate each part so that `["ab", "c"]` and `["a", "bc"]` hash differently.
+    part
+      .bytes()
+      .chain(std::iter::once(0))
+      .fold(hash, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+      })
+  });
+
+  format!("\"{:016x}\"", hash)
+}
+
+/// Whether an `If-None-Match` header value matches `etag`, using weak comparison.
+pub fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+  let strip = |tag: &str| tag.trim().trim_start_matches("W/").to_owned();
+  let etag = strip(etag);
+
+  if_none_match
+    .split(',')
+    .any(|candidate| candidate.trim() == "*" || strip(candidate) == etag)
+}
+
+#[cfg(test)]
+mod test {
+  use super::{etag, etag_matches};
+
+  #[test]
+  fn test_etag() {
+    assert_eq!(etag(["a", "b"]), etag(["a", "b"]));
+    assert_ne!(etag(["ab", "c"]), etag(["a", "bc"]));
+
+    let tag = etag(["a"]);
+    assert!(etag_matches(&tag, &tag));
+    assert!(etag_matches(&format!("\"other\", W/{}", tag), &tag));
+    assert!(etag_matches("*", &tag));
+    assert!(!etag_matches("\"other\"", &tag));
+  }
+}
@@ -0,0 +1,141 @@
+use std::{
+  collections::{BTreeMap, HashMap},
+  ops::Bound,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::mod_info::Metadata;
+
+/// Key of the manifest in `STARSECTOR_MOD_METADATA`.
+pub const MANIFEST_KEY: &str = "__index/manifest";
+
+/// Every mod id the service knows about, maintained by `persist` so the catalogue never needs to
+/// list the bucket.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Manifest {
+  pub mods: BTreeMap<String, ManifestEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ManifestEntry {
+  pub first_seen: DateTime<Utc>,
+  /// Number of distinct versions stored for the mod.
+  pub versions: usize,
+  /// Sum of `Metadata::total` across every version of the mod.
+  pub total: u64,
+}
+
+impl ManifestEntry {
+  /// Summarises the stored versions of a mod. Returns `None` if it has none.
+  pub fn summarise(versions: &HashMap<String, Metadata>) -> Option<Self> {
+    let first_seen = versions.values().map(|meta| meta.first_seen).min()?;
+
+    Some(Self {
+      first_seen,
+      versions: versions.len(),
+      total: versions.values().map(|meta| u64::from(meta.total)).sum(),
+    })
+  }
+}
+
+impl Manifest {
+  /// Replaces the entry for `id`. Returns whether anything changed.
+  ///
+  /// Totals only ever grow, so an entry with a smaller total than the stored one is a stale
+  /// summary from a concurrent `persist` and is ignored.
+  pub fn update(&mut self, id: &str, entry: ManifestEntry) -> bool {
+    if let Some(existing) = self.mods.get(id) {
+      if *existing == entry || existing.total > entry.total {
+        return false;
+      }
+    }
+
+    self.mods.insert(id.to_owned(), entry);
+    true
+  }
+
+  /// Replaces the entry for `id`, even if its total shrank, such as after compaction dropped
+  /// some of its versions. Returns whether anything changed.
+  pub fn set(&mut self, id: &str, entry: ManifestEntry) -> bool {
+    if self.mods.get(id) == Some(&entry) {
+      return false;
+    }
+
+    self.mods.insert(id.to_owned(), entry);
+    true
+  }
+
+  /// Returns up to `limit` entries in id order following `cursor`, and the cursor for the next
+  /// page if there is one.
+  pub fn page(
+    &self,
+    cursor: Option<&str>,
+    limit: usize,
+  ) -> (Vec<(&str, &ManifestEntry)>, Option<String>) {
+    let limit = limit.max(1);
+    let start = match cursor.and_then(decode_cursor) {
+      Some(after) => Bound::Excluded(after),
+      None => Bound::Unbounded,
+    };
+
+    let mut entries: Vec<(&str, &ManifestEntry)> = self
+      .mods
+      .range((start, Bound::Unbounded))
+      .take(limit + 1)
+      .map(|(id, entry)| (id.as_str(), entry))
+      .collect();
+
+    let next = (entries.len() > limit).then(|| encode_cursor(entries[limit - 1].0));
+    entries.truncate(limit);
+
+    (entries, next)
+  }
+}
+
+fn encode_cursor(id: &str) -> String {
+  base64::encode_config(id, base64::URL_SAFE_NO_PAD)
+}
+
+/// Whether `cursor` is one [`Manifest::page`] could have returned.
+pub fn is_valid_cursor(cursor: &str) -> bool {
+  decode_cursor(cursor).is_some()
+}
+
+fn decode_cursor(cursor: &str) -> Option<String> {
+  let decoded = base64::decode_config(cursor, base64::URL_SAFE_NO_PAD).ok()?;
+  String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod test {
+  use chrono::Utc;
+
+  use super::{is_valid_cursor, Manifest, ManifestEntry};
+
+  #[test]
+  fn test_manifest_page() {
+    let mut manifest = Manifest::default();
+    let entry = ManifestEntry {
+      first_seen: Utc::now(),
+      versions: 1,
+      total: 1,
+    };
+    for id in ["lw_lazylib", "MagicLib", "nexerelin"] {
+      assert!(manifest.update(id, entry.clone()));
+    }
+    assert!(!manifest.update("nexerelin", entry.clone()));
+
+    let (page, cursor) = manifest.page(None, 2);
+    let ids: Vec<&str> = page.iter().map(|(id, _)| *id).collect();
+    assert_eq!(ids, ["MagicLib", "lw_lazylib"]);
+    assert!(is_valid_cursor(cursor.as_deref().unwrap()));
+    assert!(!is_valid_cursor("not a cursor"));
+
+    let (page, cursor) = manifest.page(cursor.as_deref(), 2);
+    let ids: Vec<&str> = page.iter().map(|(id, _)| *id).collect();
+    assert_eq!(ids, ["nexerelin"]);
+    assert_eq!(cursor, None);
+  }
+}
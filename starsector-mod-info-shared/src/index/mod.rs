@@ -0,0 +1,4 @@
+pub mod manifest;
+pub mod ranking;
+pub mod related;
+pub mod search;
@@ -0,0 +1,203 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::mod_info::Metadata;
+
+/// Key of the ranking index in `STARSECTOR_MOD_METADATA`.
+pub const RANKING_KEY: &str = "__index/ranking";
+/// Entry under which installs are counted regardless of game version.
+pub const ALL_GAME_VERSIONS: &str = "*";
+/// Number of days of per-day install counts kept for windowed rankings.
+pub const RETENTION_DAYS: i64 = 30;
+
+/// Install counts per mod, maintained by `persist` so rankings never need to list the bucket.
+#[derive(Serialize, Deserialize, Default)]
+pub struct RankingIndex {
+  /// Entries keyed by game version, then by mod id.
+  pub games: HashMap<String, HashMap<String, RankEntry>>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct RankEntry {
+  /// Sum of `Metadata::total` across every version of the mod.
+  pub total: u64,
+  pub daily: BTreeMap<NaiveDate, u32>,
+}
+
+impl RankingIndex {
+  /// Counts one install of `mod_id`, both overall and for `game_version` if it is known.
+  pub fn record(&mut self, mod_id: &str, game_version: Option<&str>, now: DateTime<Utc>) {
+    let today = now.naive_utc().date();
+
+    let game_version = game_version.filter(|game_version| *game_version != ALL_GAME_VERSIONS);
+    for game in std::iter::once(ALL_GAME_VERSIONS).chain(game_version) {
+      let entry = self
+        .games
+        .entry(game.to_owned())
+        .or_default()
+        .entry(mod_id.to_owned())
+        .or_default();
+
+      entry.total += 1;
+      *entry.daily.entry(today).or_default() += 1;
+    }
+  }
+
+  /// Drops per-day counts that have fallen out of the retention window.
+  pub fn prune(&mut self, now: DateTime<Utc>) {
+    let cutoff = now.naive_utc().date() - Duration::days(RETENTION_DAYS);
+
+    for entry in self.games.values_mut().flat_map(HashMap::values_mut) {
+      entry.daily = entry.daily.split_off(&cutoff);
+    }
+  }
+
+  /// Replaces the lifetime totals of `mod_id` with those summed from its stored versions,
+  /// keeping its per-day counts. This corrects drift, such as from versions dropped by
+  /// compaction. Game versions none of its stored versions have are zeroed, and forgotten once
+  /// their per-day counts have expired too.
+  pub fn resync(&mut self, mod_id: &str, versions: &HashMap<String, Metadata>) {
+    let mut totals: HashMap<&str, u64> = HashMap::new();
+    for meta in versions.values() {
+      *totals.entry(ALL_GAME_VERSIONS).or_default() += u64::from(meta.total);
+      for (game_version, count) in &meta.game_versions {
+        if game_version == ALL_GAME_VERSIONS {
+          continue;
+        }
+        *totals.entry(game_version).or_default() += u64::from(*count);
+      }
+    }
+
+    self.games.retain(|game, entries| {
+      if !totals.contains_key(game.as_str()) {
+        if let Some(entry) = entries.get_mut(mod_id) {
+          entry.total = 0;
+          if entry.daily.is_empty() {
+            entries.remove(mod_id);
+          }
+        }
+      }
+      !entries.is_empty()
+    });
+
+    for (game, total) in totals {
+      self
+        .games
+        .entry(game.to_owned())
+        .or_default()
+        .entry(mod_id.to_owned())
+        .or_default()
+        .total = total;
+    }
+  }
+
+  /// Lifetime installs of every mod, regardless of game version.
+  pub fn totals(&self) -> HashMap<&str, u64> {
+    self
+      .games
+      .get(ALL_GAME_VERSIONS)
+      .map(|entries| {
+        entries
+          .iter()
+          .map(|(id, entry)| (id.as_str(), entry.total))
+          .collect()
+      })
+      .unwrap_or_default()
+  }
+
+  /// Returns the `n` most installed mods, optionally restricted to a game version and to
+  /// installs over the last `days` days.
+  pub fn top(
+    &self,
+    n: usize,
+    game_version: Option<&str>,
+    days: Option<i64>,
+    now: DateTime<Utc>,
+  ) -> Vec<(&str, u64)> {
+    let Some(entries) = self.games.get(game_version.unwrap_or(ALL_GAME_VERSIONS)) else {
+      return Vec::new();
+    };
+
+    let mut ranked: Vec<(&str, u64)> = entries
+      .iter()
+      .map(|(id, entry)| {
+        let installs = match days {
+          Some(days) => {
+            let since = now.naive_utc().date() - Duration::days(days - 1);
+            entry
+              .daily
+              .range(since..)
+              .map(|(_, count)| u64::from(*count))
+              .sum()
+          }
+          None => entry.total,
+        };
+
+        (id.as_str(), installs)
+      })
+      .filter(|(_, installs)| *installs > 0)
+      .collect();
+
+    ranked.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    ranked.truncate(n);
+
+    ranked
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::collections::HashMap;
+
+  use chrono::{Duration, Utc};
+
+  use super::RankingIndex;
+  use crate::mod_info::Metadata;
+
+  #[test]
+  fn test_ranking() {
+    let now = Utc::now();
+    let mut index = RankingIndex::default();
+
+    index.record("lw_lazylib", Some("0.95.1a"), now - Duration::days(10));
+    index.record("lw_lazylib", Some("0.96a"), now);
+    index.record("MagicLib", Some("0.96a"), now);
+    index.record("MagicLib", None, now);
+    index.record("nexerelin", None, now);
+
+    assert_eq!(
+      index.top(2, None, None, now),
+      vec![("MagicLib", 2), ("lw_lazylib", 2)]
+    );
+    assert_eq!(
+      index.top(10, Some("0.96a"), None, now),
+      vec![("MagicLib", 1), ("lw_lazylib", 1)]
+    );
+    assert_eq!(index.top(1, None, Some(7), now), vec![("MagicLib", 2)]);
+    assert!(index.top(10, Some("0.9a"), None, now).is_empty());
+  }
+
+  #[test]
+  fn test_resync_zeroes_dropped_game_versions() {
+    let now = Utc::now();
+    let mut index = RankingIndex::default();
+    index.record("lw_lazylib", Some("0.95a"), now - Duration::days(60));
+    index.record("lw_lazylib", Some("0.96a"), now);
+    index.prune(now);
+
+    // Compaction dropped the only version submitted from 0.95a.
+    let mut meta = Metadata::new(now);
+    meta.record(Some("a"), Some("0.96a"), now);
+    index.resync("lw_lazylib", &HashMap::from([("1.0".to_owned(), meta)]));
+
+    assert!(index.top(10, Some("0.95a"), None, now).is_empty());
+    assert!(!index.games.contains_key("0.95a"));
+    assert_eq!(
+      index.top(10, Some("0.96a"), None, now),
+      vec![("lw_lazylib", 1)]
+    );
+    assert_eq!(index.top(10, None, None, now), vec![("lw_lazylib", 1)]);
+  }
+}
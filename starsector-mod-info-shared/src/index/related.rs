@@ -0,0 +1,135 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use super::ranking::RankingIndex;
+
+/// Prefix of the shards of co-installation counts in `STARSECTOR_MOD_METADATA`.
+pub const RELATED_PREFIX: &str = "__index/related/";
+/// Number of shards the co-installation counts are spread over, so that applying a submission
+/// touches at most this many objects however many mods it holds.
+pub const RELATED_SHARDS: u32 = 16;
+
+/// The related indexes of every mod in a single shard, by mod id.
+pub type RelatedShard = HashMap<String, RelatedIndex>;
+
+/// Shard holding the related index of mod `id`.
+///
+/// Hashed with FNV-1a rather than `std`'s hasher, whose output may change between Rust releases
+/// and would then send lookups to the wrong shard.
+pub fn related_shard(id: &str) -> u32 {
+  let hash = id.bytes().fold(0x811c_9dc5_u32, |hash, byte| {
+    (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+  });
+  hash % RELATED_SHARDS
+}
+
+pub fn related_key(shard: u32) -> String {
+  format!("{}{:02}", RELATED_PREFIX, shard)
+}
+
+/// Co-installation counts for a single mod: how many counted installs of it there were, and how
+/// many of those also contained each other mod.
+///
+/// Like a version's `total`, an install only counts the first time its user submits the mod.
+/// Unlike the ranking, these counts cannot be rebuilt from the stored mods, which do not record
+/// what was installed alongside them.
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct RelatedIndex {
+  pub installs: u64,
+  pub related: HashMap<String, u64>,
+}
+
+impl RelatedIndex {
+  /// Counts a submission containing this mod alongside `others`.
+  pub fn record<'a>(&mut self, id: &str, others: impl IntoIterator<Item = &'a str>) {
+    self.installs += 1;
+
+    let others: HashSet<&str> = others.into_iter().filter(|other| *other != id).collect();
+    for other in others {
+      *self.related.entry(other.to_owned()).or_default() += 1;
+    }
+  }
+
+  /// Adds counts recorded elsewhere, such as in an in-memory batch, to this index.
+  pub fn merge(&mut self, other: RelatedIndex) {
+    self.installs += other.installs;
+    for (id, count) in other.related {
+      *self.related.entry(id).or_default() += count;
+    }
+  }
+
+  /// Returns the `n` mods most often installed alongside this one.
+  ///
+  /// Raw counts favour mods almost everyone runs, such as LazyLib, so each pair is scored by its
+  /// cosine similarity, `co_installs / sqrt(installs(self) * installs(other))`, using the
+  /// ranking index for the popularity of the other mod.
+  pub fn top(&self, n: usize, ranking: &RankingIndex) -> Vec<(&str, u64, f64)> {
+    let popularity = ranking.totals();
+
+    let mut scored: Vec<(&str, u64, f64)> = self
+      .related
+      .iter()
+      .map(|(other, co_installs)| {
+        let other_installs = popularity
+          .get(other.as_str())
+          .copied()
+          .unwrap_or(*co_installs)
+          .max(*co_installs);
+        let score = *co_installs as f64 / ((self.installs.max(1) * other_installs) as f64).sqrt();
+
+        (other.as_str(), *co_installs, score)
+      })
+      .collect();
+
+    scored.sort_unstable_by(|a, b| b.2.total_cmp(&a.2).then_with(|| a.0.cmp(b.0)));
+    scored.truncate(n);
+
+    scored
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use chrono::Utc;
+
+  use super::{related_shard, RelatedIndex};
+  use crate::index::ranking::RankingIndex;
+
+  #[test]
+  fn test_related_shard_is_stable() {
+    assert_eq!(related_shard("nexerelin"), 13);
+    assert_eq!(related_shard("lw_lazylib"), 10);
+    assert_eq!(related_shard("diableavionics"), 2);
+  }
+
+  #[test]
+  fn test_related_normalized_by_popularity() {
+    let now = Utc::now();
+    let mut ranking = RankingIndex::default();
+    let mut index = RelatedIndex::default();
+
+    let submissions = [
+      vec!["nexerelin", "lw_lazylib", "diableavionics"],
+      vec!["nexerelin", "lw_lazylib", "diableavionics"],
+      vec!["nexerelin", "lw_lazylib"],
+      vec!["lw_lazylib"],
+      vec!["lw_lazylib"],
+      vec!["lw_lazylib"],
+    ];
+    for submission in &submissions {
+      for id in submission {
+        ranking.record(id, None, now);
+      }
+      if submission.contains(&"nexerelin") {
+        index.record("nexerelin", submission.iter().copied());
+      }
+    }
+
+    let top = index.top(2, &ranking);
+    assert_eq!(index.installs, 3);
+    assert_eq!(top[0].0, "diableavionics");
+    assert_eq!(top[1].0, "lw_lazylib");
+    assert_eq!(top[1].1, 3);
+  }
+}
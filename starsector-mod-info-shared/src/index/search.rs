@@ -0,0 +1,183 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+/// Key of the search index in `STARSECTOR_MOD_METADATA`.
+pub const SEARCH_KEY: &str = "__index/search";
+/// Maximum number of display names remembered per mod.
+pub const MAX_NAMES: usize = 5;
+
+/// Every known mod id along with the display names it has been submitted with.
+#[derive(Serialize, Deserialize, Default)]
+pub struct SearchIndex {
+  pub mods: BTreeMap<String, BTreeSet<String>>,
+}
+
+/// How closely a mod matched a query, from best to worst.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Match {
+  Exact,
+  IdPrefix,
+  NamePrefix,
+  IdSubstring,
+  NameSubstring,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Hit<'a> {
+  pub id: &'a str,
+  pub names: &'a BTreeSet<String>,
+  #[serde(rename = "match")]
+  pub kind: Match,
+}
+
+impl SearchIndex {
+  /// Adds `id` to the index, along with `name` if one was submitted. Returns whether the index
+  /// changed and so needs writing back.
+  pub fn insert(&mut self, id: &str, name: Option<&str>) -> bool {
+    let name = name.map(str::trim).filter(|name| !name.is_empty());
+
+    match self.mods.get_mut(id) {
+      Some(names) => match name {
+        Some(name) if names.len() < MAX_NAMES && !names.contains(name) => {
+          names.insert(name.to_owned())
+        }
+        _ => false,
+      },
+      None => {
+        self
+          .mods
+          .insert(id.to_owned(), name.map(str::to_owned).into_iter().collect());
+        true
+      }
+    }
+  }
+
+  /// Searches ids and display names for `query`, case-insensitively.
+  ///
+  /// Results are ordered by how well they matched and then by id. Returns up to `limit` hits
+  /// following `cursor`, and the cursor for the next page if there is one.
+  pub fn search(
+    &self,
+    query: &str,
+    cursor: Option<&str>,
+    limit: usize,
+  ) -> (Vec<Hit<'_>>, Option<String>) {
+    let limit = limit.max(1);
+    let query = query.trim().to_lowercase();
+    let after = cursor.and_then(decode_cursor);
+
+    let mut hits: Vec<Hit> = self
+      .mods
+      .iter()
+      .filter_map(|(id, names)| {
+        Some(Hit {
+          id,
+          names,
+          kind: match_kind(&query, id, names)?,
+        })
+      })
+      .filter(|hit| match &after {
+        Some((kind, id)) => (hit.kind, hit.id) > (*kind, id.as_str()),
+        None => true,
+      })
+      .collect();
+    hits.sort_unstable_by(|a, b| (a.kind, a.id).cmp(&(b.kind, b.id)));
+
+    let next = (hits.len() > limit).then(|| encode_cursor(&hits[limit - 1]));
+    hits.truncate(limit);
+
+    (hits, next)
+  }
+}
+
+fn match_kind(query: &str, id: &str, names: &BTreeSet<String>) -> Option<Match> {
+  let id = id.to_lowercase();
+  let names: Vec<String> = names.iter().map(|name| name.to_lowercase()).collect();
+
+  if id == query || names.iter().any(|name| *name == query) {
+    Some(Match::Exact)
+  } else if id.starts_with(query) {
+    Some(Match::IdPrefix)
+  } else if names.iter().any(|name| name.starts_with(query)) {
+    Some(Match::NamePrefix)
+  } else if id.contains(query) {
+    Some(Match::IdSubstring)
+  } else if names.iter().any(|name| name.contains(query)) {
+    Some(Match::NameSubstring)
+  } else {
+    None
+  }
+}
+
+fn encode_cursor(hit: &Hit) -> String {
+  base64::encode_config(
+    format!("{}:{}", hit.kind as u8, hit.id),
+    base64::URL_SAFE_NO_PAD,
+  )
+}
+
+/// Whether `cursor` is one [`SearchIndex::search`] could have returned.
+pub fn is_valid_cursor(cursor: &str) -> bool {
+  decode_cursor(cursor).is_some()
+}
+
+fn decode_cursor(cursor: &str) -> Option<(Match, String)> {
+  let decoded = base64::decode_config(cursor, base64::URL_SAFE_NO_PAD).ok()?;
+  let (kind, id) = String::from_utf8(decoded)
+    .ok()?
+    .split_once(':')
+    .map(|(kind, id)| (kind.to_owned(), id.to_owned()))?;
+
+  let kind = match kind.parse::<u8>().ok()? {
+    0 => Match::Exact,
+    1 => Match::IdPrefix,
+    2 => Match::NamePrefix,
+    3 => Match::IdSubstring,
+    4 => Match::NameSubstring,
+    _ => return None,
+  };
+
+  Some((kind, id))
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Match, SearchIndex};
+
+  #[test]
+  fn test_search() {
+    let mut index = SearchIndex::default();
+    assert!(index.insert("lw_lazylib", Some("LazyLib")));
+    assert!(index.insert("MagicLib", Some("MagicLib")));
+    assert!(index.insert("nexerelin", Some("Nexerelin")));
+    assert!(index.insert("shadow_lib", None));
+    assert!(!index.insert("shadow_lib", None));
+
+    let (hits, next) = index.search("lib", None, 10);
+    assert!(next.is_none());
+    assert_eq!(
+      hits
+        .iter()
+        .map(|hit| (hit.id, hit.kind))
+        .collect::<Vec<_>>(),
+      vec![
+        ("MagicLib", Match::IdSubstring),
+        ("lw_lazylib", Match::IdSubstring),
+        ("shadow_lib", Match::IdSubstring),
+      ]
+    );
+
+    let (hits, next) = index.search("lazy", None, 10);
+    assert_eq!(hits[0].kind, Match::NamePrefix);
+    assert!(next.is_none());
+
+    let (first, next) = index.search("lib", None, 2);
+    assert_eq!(first.len(), 2);
+    let (rest, next) = index.search("lib", next.as_deref(), 2);
+    assert_eq!(rest.len(), 1);
+    assert_eq!(rest[0].id, "shadow_lib");
+    assert!(next.is_none());
+  }
+}
@@ -10,8 +10,13 @@ use worker_result_ext::ResultExt;
 
 pub mod amqp;
 pub mod cache;
+pub mod index;
+pub mod message;
 pub mod middleware;
 pub mod mod_info;
+pub mod publisher;
+pub mod signature;
+pub mod storage;
 pub mod user;
 pub mod worker_result_ext;
 
@@ -0,0 +1,187 @@
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use strum::VariantNames;
+
+use crate::{
+  amqp::{DeliveryMode, Properties},
+  mod_info::Submission,
+};
+
+/// Version of the message format, sent in each [`Envelope`] and as the schema version header.
+/// Version 1 was a bare list of mods, and version 2 a bare [`Submission`].
+pub const MESSAGE_SCHEMA_VERSION: u32 = 3;
+
+/// Queue the storage worker consumes, on the broker's default exchange.
+pub const ROUTING_KEY: &str = "write";
+
+/// A message to the storage worker, along with the version of the format it was published in.
+///
+/// Serialized as `{"version": 3, "kind": "installed_mods", "body": {..}}`.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(try_from = "RawEnvelope")]
+pub struct Envelope {
+  pub version: u32,
+  #[serde(flatten)]
+  pub message: Message,
+}
+
+impl Envelope {
+  pub fn new(message: Message) -> Self {
+    Envelope {
+      version: MESSAGE_SCHEMA_VERSION,
+      message,
+    }
+  }
+}
+
+/// Every kind of message the storage worker handles.
+#[derive(Serialize, Deserialize, Debug, strum::IntoStaticStr, strum::EnumVariantNames)]
+#[serde(tag = "kind", content = "body", rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Message {
+  InstalledMods(Submission),
+  UserCreated(UserCreated),
+  ScoreUpdated(ScoreUpdated),
+}
+
+impl Message {
+  pub fn kind(&self) -> &'static str {
+    self.into()
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserCreated {
+  pub user_id: String,
+  pub created_at: DateTime<Utc>,
+}
+
+impl UserCreated {
+  pub fn new(user_id: String) -> Self {
+    UserCreated {
+      user_id,
+      created_at: Utc::now(),
+    }
+  }
+}
+
+/// Sent when a user reaches a new high score, rather than on every change to their score.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScoreUpdated {
+  pub user_id: String,
+  pub high_score: u32,
+  pub updated_at: DateTime<Utc>,
+}
+
+impl ScoreUpdated {
+  pub fn new(user_id: String, high_score: u32) -> Self {
+    ScoreUpdated {
+      user_id,
+      high_score,
+      updated_at: Utc::now(),
+    }
+  }
+}
+
+/// Properties for a JSON message published now, that the broker should keep until delivered.
+pub fn properties() -> Properties {
+  Properties::default()
+    .content_type("application/json")
+    .delivery_mode(DeliveryMode::Persistent)
+    .timestamp(Utc::now().timestamp())
+}
+
+#[derive(Debug)]
+pub enum EnvelopeError {
+  /// Published in a newer format than this build understands.
+  UnsupportedVersion(u32),
+  /// A kind with no handler in this build.
+  UnknownKind(String),
+  /// The body does not match its kind.
+  Malformed(serde_json::Error),
+}
+
+impl fmt::Display for EnvelopeError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      EnvelopeError::UnsupportedVersion(version) => write!(
+        f,
+        "Message version {} is newer than the supported {}",
+        version, MESSAGE_SCHEMA_VERSION
+      ),
+      EnvelopeError::UnknownKind(kind) => write!(f, "Unknown message kind: {}", kind),
+      EnvelopeError::Malformed(err) => write!(f, "Malformed message body: {}", err),
+    }
+  }
+}
+
+impl std::error::Error for EnvelopeError {}
+
+#[derive(Deserialize)]
+struct RawEnvelope {
+  version: u32,
+  kind: String,
+  #[serde(default)]
+  body: Value,
+}
+
+impl TryFrom<RawEnvelope> for Envelope {
+  type Error = EnvelopeError;
+
+  fn try_from(raw: RawEnvelope) -> Result<Self, Self::Error> {
+    if raw.version > MESSAGE_SCHEMA_VERSION {
+      return Err(EnvelopeError::UnsupportedVersion(raw.version));
+    }
+    if !Message::VARIANTS.contains(&raw.kind.as_str()) {
+      return Err(EnvelopeError::UnknownKind(raw.kind));
+    }
+
+    let message = serde_json::from_value(json!({ "kind": raw.kind, "body": raw.body }))
+      .map_err(EnvelopeError::Malformed)?;
+
+    Ok(Envelope {
+      version: raw.version,
+      message,
+    })
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use serde_json::json;
+
+  use super::{Envelope, Message, UserCreated, MESSAGE_SCHEMA_VERSION};
+
+  #[test]
+  fn test_envelope() {
+    let created = UserCreated::new("abc".to_owned());
+    let json = serde_json::to_value(Envelope::new(Message::UserCreated(created.clone()))).unwrap();
+
+    assert_eq!(json["version"], MESSAGE_SCHEMA_VERSION);
+    assert_eq!(json["kind"], "user_created");
+
+    let envelope: Envelope = serde_json::from_value(json).unwrap();
+    assert!(matches!(envelope.message, Message::UserCreated(user) if user == created));
+
+    let submission: Envelope = serde_json::from_value(json!({
+      "version": 2,
+      "kind": "installed_mods",
+      "body": { "mods": [] },
+    }))
+    .unwrap();
+    assert_eq!(submission.message.kind(), "installed_mods");
+
+    let unknown = serde_json::from_value::<Envelope>(json!({
+      "version": MESSAGE_SCHEMA_VERSION,
+      "kind": "user_deleted",
+      "body": {},
+    }));
+    assert!(unknown
+      .unwrap_err()
+      .to_string()
+      .contains("Unknown message kind: user_deleted"));
+  }
+}
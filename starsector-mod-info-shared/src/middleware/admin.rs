@@ -0,0 +1,50 @@
+use worker::{Request, Response, RouteContext};
+
+use crate::{signature::constant_time_eq, worker_result_ext::ResultExt};
+
+/// Checks for a bearer token in the Authorization header matching the `ADMIN_KEY` secret.
+///
+/// # Examples
+///
+/// ```
+/// use starsector_mod_info_shared::authorize_admin;
+///
+/// async fn route<D>(req: worker::Request, ctx: worker::RouteContext<D>) -> worker::Result<worker::Response> {
+///   authorize_admin!(&req, &ctx);
+///
+///   worker::Response::ok("OK")
+/// }
+/// ```
+#[macro_export]
+macro_rules! authorize_admin {
+  ($req:expr, $ctx:expr) => {
+    if let Some(res) =
+      starsector_mod_info_shared::middleware::admin::authorize_admin_internal($req, $ctx)
+        .transpose()
+    {
+      return res;
+    };
+  };
+}
+
+pub fn authorize_admin_internal<D>(
+  req: &Request,
+  ctx: &RouteContext<D>,
+) -> worker::Result<Option<Response>> {
+  let Some(token) = req
+    .headers()
+    .get("Authorization")
+    .conv()?
+    .and_then(|auth| auth.strip_prefix("Bearer ").map(str::to_owned))
+  else {
+    return Response::error("Authorization header malformed or missing", 400).map(Some);
+  };
+
+  let key = ctx.secret("ADMIN_KEY")?.to_string();
+
+  if constant_time_eq(&token, &key) {
+    Ok(None)
+  } else {
+    Response::error("Invalid admin key", 401).map(Some)
+  }
+}
@@ -57,6 +57,22 @@ pub async fn authenticate_internal<D>(
   }
 }
 
+/// Returns the id of the user named in the Authorization header, without checking their
+/// password. Only use this in routes already guarded by [`authenticate!`](crate::authenticate).
+pub fn user_id(req: &Request) -> worker::Result<Option<String>> {
+  Ok(
+    req
+      .headers()
+      .get("Authorization")
+      .conv()?
+      .map(parse_auth_header)
+      .transpose()
+      .conv()?
+      .flatten()
+      .map(|(user, _)| user),
+  )
+}
+
 fn parse_auth_header(auth: String) -> worker::Result<Option<(String, String)>> {
   let Some(val) = auth.strip_prefix("Basic ") else {
     return Ok(None);
@@ -1,2 +1,5 @@
+pub mod admin;
 pub mod authentication;
+pub mod query;
 pub mod rate_limit;
+pub mod signature;
@@ -0,0 +1,45 @@
+use std::str::FromStr;
+
+use worker::{Response, Url};
+
+/// Parses the query parameter `name` if it is present, answering with a 400 that names the
+/// parameter when it does not parse.
+///
+/// # Examples
+///
+/// ```
+/// use starsector_mod_info_shared::query_param;
+///
+/// async fn route(req: worker::Request) -> worker::Result<worker::Response> {
+///   let url = req.url()?;
+///   let limit: Option<u32> = query_param!(&url, "limit");
+///
+///   worker::Response::ok(format!("{:?}", limit))
+/// }
+/// ```
+#[macro_export]
+macro_rules! query_param {
+  ($url:expr, $name:expr) => {
+    match starsector_mod_info_shared::middleware::query::query_param_internal($url, $name) {
+      Ok(value) => value,
+      Err(res) => return res,
+    }
+  };
+}
+
+pub fn query_param_internal<T: FromStr>(
+  url: &Url,
+  name: &str,
+) -> Result<Option<T>, worker::Result<Response>> {
+  let Some(value) = url
+    .query_pairs()
+    .find_map(|(key, val)| (key == name).then(|| val.into_owned()))
+  else {
+    return Ok(None);
+  };
+
+  value
+    .parse()
+    .map(Some)
+    .map_err(|_| Response::error(format!("Invalid {}: {}", name, value), 400))
+}
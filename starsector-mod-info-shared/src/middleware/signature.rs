@@ -0,0 +1,66 @@
+use chrono::Utc;
+use worker::{Request, Response, RouteContext};
+
+use crate::{
+  signature::{verify, SignatureError, DEFAULT_TOLERANCE_SECS, SIGNATURE_HEADER},
+  worker_result_ext::ResultExt,
+};
+
+/// Checks that `body` is signed with the `WEBHOOK_SIGNING_KEY` secret, or with
+/// `WEBHOOK_SIGNING_KEY_PREVIOUS` while keys are being rotated. See [`crate::signature`].
+///
+/// The accepted clock skew can be set through the `WEBHOOK_TOLERANCE_SECS` variable.
+///
+/// # Examples
+///
+/// ```
+/// use starsector_mod_info_shared::verify_signature;
+///
+/// async fn route<D>(mut req: worker::Request, ctx: worker::RouteContext<D>) -> worker::Result<worker::Response> {
+///   let body = req.text().await?;
+///   verify_signature!(&req, &ctx, &body);
+///
+///   worker::Response::ok("OK")
+/// }
+/// ```
+#[macro_export]
+macro_rules! verify_signature {
+  ($req:expr, $ctx:expr, $body:expr) => {
+    if let Some(res) = starsector_mod_info_shared::middleware::signature::verify_signature_internal(
+      $req, $ctx, $body,
+    )
+    .transpose()
+    {
+      return res;
+    };
+  };
+}
+
+pub fn verify_signature_internal<D>(
+  req: &Request,
+  ctx: &RouteContext<D>,
+  body: &str,
+) -> worker::Result<Option<Response>> {
+  let Some(header) = req.headers().get(SIGNATURE_HEADER).conv()? else {
+    return Response::error("Signature header missing", 400).map(Some);
+  };
+
+  let current = ctx.secret("WEBHOOK_SIGNING_KEY")?.to_string();
+  let previous = ctx
+    .secret("WEBHOOK_SIGNING_KEY_PREVIOUS")
+    .ok()
+    .map(|key| key.to_string());
+  let tolerance = ctx
+    .var("WEBHOOK_TOLERANCE_SECS")
+    .ok()
+    .and_then(|tolerance| tolerance.to_string().parse::<i64>().ok())
+    .unwrap_or(DEFAULT_TOLERANCE_SECS);
+
+  let keys = std::iter::once(current.as_str()).chain(previous.as_deref());
+  match verify(&header, body, keys, Utc::now().timestamp(), tolerance) {
+    Ok(()) => Ok(None),
+    Err(SignatureError::Malformed) => Response::error("Signature header malformed", 400).map(Some),
+    Err(SignatureError::Expired) => Response::error("Signature expired", 401).map(Some),
+    Err(SignatureError::Mismatch) => Response::error("Invalid signature", 401).map(Some),
+  }
+}
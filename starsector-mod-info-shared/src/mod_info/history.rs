@@ -0,0 +1,105 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Number of days submissions are kept at daily resolution.
+pub const DAILY_RETENTION_DAYS: i64 = 30;
+/// Number of weeks submissions are kept at weekly resolution, before rolling into months.
+pub const WEEKLY_RETENTION_WEEKS: i64 = 26;
+
+/// Submission counts over time for a single mod version.
+///
+/// Recent submissions are bucketed per day. Older buckets roll up into weeks, keyed by their
+/// Monday, and then into months, keyed by their first day, so the history stays bounded.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub struct History {
+  #[serde(default)]
+  pub daily: BTreeMap<NaiveDate, u32>,
+  #[serde(default)]
+  pub weekly: BTreeMap<NaiveDate, u32>,
+  #[serde(default)]
+  pub monthly: BTreeMap<NaiveDate, u32>,
+}
+
+impl History {
+  /// A history holding a single submission made at `now`.
+  pub fn starting(now: DateTime<Utc>) -> Self {
+    let mut history = History::default();
+    history.record(now);
+    history
+  }
+
+  pub fn record(&mut self, now: DateTime<Utc>) {
+    *self.daily.entry(now.naive_utc().date()).or_default() += 1;
+    self.roll_up(now);
+  }
+
+  /// Moves buckets that have aged out of their resolution into the next coarser one. Returns
+  /// whether any bucket moved.
+  pub fn roll_up(&mut self, now: DateTime<Utc>) -> bool {
+    let today = now.naive_utc().date();
+    let mut moved = false;
+
+    let daily_cutoff = today - Duration::days(DAILY_RETENTION_DAYS);
+    let recent = self.daily.split_off(&daily_cutoff);
+    for (day, count) in std::mem::replace(&mut self.daily, recent) {
+      *self.weekly.entry(week_start(day)).or_default() += count;
+      moved = true;
+    }
+
+    let weekly_cutoff = week_start(today) - Duration::weeks(WEEKLY_RETENTION_WEEKS);
+    let recent = self.weekly.split_off(&weekly_cutoff);
+    for (week, count) in std::mem::replace(&mut self.weekly, recent) {
+      *self.monthly.entry(month_start(week)).or_default() += count;
+      moved = true;
+    }
+
+    moved
+  }
+
+  pub fn merge(&mut self, other: History) {
+    for (target, source) in [
+      (&mut self.daily, other.daily),
+      (&mut self.weekly, other.weekly),
+      (&mut self.monthly, other.monthly),
+    ] {
+      for (date, count) in source {
+        *target.entry(date).or_default() += count;
+      }
+    }
+  }
+}
+
+fn week_start(day: NaiveDate) -> NaiveDate {
+  day - Duration::days(day.weekday().num_days_from_monday().into())
+}
+
+fn month_start(day: NaiveDate) -> NaiveDate {
+  day.with_day(1).unwrap_or(day)
+}
+
+#[cfg(test)]
+mod test {
+  use chrono::{Duration, NaiveDate, TimeZone, Utc};
+
+  use super::History;
+
+  #[test]
+  fn test_history_roll_up() {
+    let start = Utc.with_ymd_and_hms(2023, 1, 2, 12, 0, 0).unwrap();
+    let mut history = History::starting(start);
+    history.record(start + Duration::days(1));
+
+    let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+
+    history.roll_up(start + Duration::days(40));
+    assert!(history.daily.is_empty());
+    assert_eq!(history.weekly.get(&date(2023, 1, 2)), Some(&2));
+
+    history.record(start + Duration::days(365));
+    assert!(history.weekly.is_empty());
+    assert_eq!(history.monthly.get(&date(2023, 1, 1)), Some(&2));
+    assert_eq!(history.daily.values().sum::<u32>(), 1);
+  }
+}
@@ -1,17 +1,109 @@
-use std::{collections::HashMap, fmt::Display};
+use std::{
+  cmp::Ordering,
+  collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap},
+  fmt::Display,
+};
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_aux::prelude::*;
+use uuid::Uuid;
 
-use crate::ScoreKey;
+use crate::{index::ranking::ALL_GAME_VERSIONS, ScoreKey};
 
 mod durable_mod;
+pub mod history;
+pub mod normalize;
+pub mod retention;
+pub mod schema;
+pub mod version;
+
+pub use history::History;
+pub use schema::StoredMod;
+pub use version::ParsedVersion;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Mod {
   pub id: String,
   pub version: Version,
+  /// Display name from the mod's `mod_info.json`, if the client sent one.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub name: Option<String>,
+}
+
+/// A client's list of installed mods, along with the game version they are running.
+///
+/// Deserializes from either the full envelope or a bare list of mods, as sent by older clients.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(from = "SubmissionRepr")]
+pub struct Submission {
+  /// Unique id assigned when the submission is published, used to skip redeliveries.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub id: Option<Uuid>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub game_version: Option<String>,
+  /// Authenticated user that made the submission, used to count each installer only once.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub user_id: Option<String>,
+  pub mods: Vec<Mod>,
+}
+
+impl Submission {
+  /// Assigns a fresh unique id, replacing any the client may have sent.
+  pub fn with_new_id(mut self) -> Self {
+    self.id = Some(Uuid::new_v4());
+    self
+  }
+
+  /// Attributes the submission to `user_id`, replacing any the client may have sent.
+  pub fn with_user_id(mut self, user_id: String) -> Self {
+    self.user_id = Some(user_id);
+    self
+  }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SubmissionRepr {
+  Bare(Vec<Mod>),
+  Envelope {
+    #[serde(default)]
+    id: Option<Uuid>,
+    #[serde(default)]
+    game_version: Option<String>,
+    #[serde(default)]
+    user_id: Option<String>,
+    mods: Vec<Mod>,
+  },
+}
+
+impl From<SubmissionRepr> for Submission {
+  fn from(value: SubmissionRepr) -> Self {
+    match value {
+      SubmissionRepr::Bare(mods) => Submission {
+        id: None,
+        game_version: None,
+        user_id: None,
+        mods,
+      },
+      SubmissionRepr::Envelope {
+        id,
+        game_version,
+        user_id,
+        mods,
+      } => Submission {
+        id,
+        // The ranking index counts every game version together under `ALL_GAME_VERSIONS`, so a
+        // client claiming that version is treated as not saying.
+        game_version: game_version
+          .filter(|game_version| !game_version.trim().is_empty())
+          .map(|game_version| normalize::game_version_key(&game_version))
+          .filter(|game_version| game_version != ALL_GAME_VERSIONS),
+        user_id,
+        mods,
+      },
+    }
+  }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -21,6 +113,39 @@ pub enum Version {
   Object(VersionObj),
 }
 
+impl Version {
+  /// Parses this version into its structured, comparable form.
+  pub fn parsed(&self) -> Option<ParsedVersion> {
+    ParsedVersion::parse(&self.to_string())
+  }
+
+  /// Key under which metadata for this version is stored. Equivalent spellings, such as
+  /// `v1.2.0` and `{"major":1,"minor":2}`, share a key. See [`normalize::canonical_key`].
+  pub fn key(&self) -> String {
+    normalize::canonical_key(&self.to_string())
+  }
+}
+
+impl PartialEq for Version {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other) == Ordering::Equal
+  }
+}
+
+impl Eq for Version {}
+
+impl PartialOrd for Version {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for Version {
+  fn cmp(&self, other: &Self) -> Ordering {
+    version::compare(&self.to_string(), &other.to_string())
+  }
+}
+
 impl Display for Version {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
     let output: String = match self {
@@ -52,21 +177,255 @@ impl Display for VersionObj {
   }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Metadata {
+  /// Number of distinct users that have submitted this version. Submissions without a user, as
+  /// sent before installers were tracked, each count once.
   pub total: u32,
   pub canonical: bool,
   pub first_seen: DateTime<Utc>,
+  #[serde(default)]
+  pub last_seen: Option<DateTime<Utc>>,
+  /// Users that have submitted this version, with how many times they did.
   pub contributors: HashMap<String, ScoreKey>,
+  /// When each contributor last submitted this version, so inactive ones can be pruned.
+  #[serde(default)]
+  pub contributor_last_seen: HashMap<String, DateTime<Utc>>,
+  /// Raw spellings of this version that have been submitted or merged into it.
+  #[serde(default)]
+  pub aliases: BTreeSet<String>,
+  #[serde(default)]
+  pub history: History,
+  /// Distinct users of this version per game version they first submitted it with.
+  #[serde(default)]
+  pub game_versions: BTreeMap<String, u32>,
+  /// The game version each contributor is counted under in `game_versions`.
+  #[serde(default)]
+  pub contributor_game_versions: HashMap<String, String>,
 }
 
-impl Default for Metadata {
-  fn default() -> Self {
+impl Metadata {
+  /// Metadata for a version that has not been submitted yet.
+  pub fn new(now: DateTime<Utc>) -> Self {
     Self {
-      total: 1,
+      total: 0,
       canonical: false,
-      first_seen: Utc::now(),
+      first_seen: now,
+      last_seen: None,
       contributors: HashMap::new(),
+      contributor_last_seen: HashMap::new(),
+      aliases: BTreeSet::new(),
+      history: History::default(),
+      game_versions: BTreeMap::new(),
+      contributor_game_versions: HashMap::new(),
     }
   }
+
+  /// Counts a further submission of this version, made by `user_id` if known.
+  ///
+  /// Every submission shows up in `history`, but `total` and `game_versions` only grow for users
+  /// that had not submitted this version before. Returns whether the user was new.
+  pub fn record(
+    &mut self,
+    user_id: Option<&str>,
+    game_version: Option<&str>,
+    now: DateTime<Utc>,
+  ) -> bool {
+    self.last_seen = Some(now);
+    self.history.record(now);
+    if let Some(user_id) = user_id {
+      self.contributor_last_seen.insert(user_id.to_owned(), now);
+    }
+
+    let new = match user_id.map(|user_id| self.contributors.entry(user_id.to_owned())) {
+      Some(Entry::Occupied(mut entry)) => {
+        add_score(entry.get_mut(), 1);
+        false
+      }
+      Some(Entry::Vacant(entry)) => {
+        entry.insert(ScoreKey::Score(1));
+        true
+      }
+      None => true,
+    };
+
+    if new {
+      self.total += 1;
+      if let Some(game_version) = game_version {
+        *self
+          .game_versions
+          .entry(game_version.to_owned())
+          .or_default() += 1;
+        if let Some(user_id) = user_id {
+          self
+            .contributor_game_versions
+            .insert(user_id.to_owned(), game_version.to_owned());
+        }
+      }
+    }
+
+    new
+  }
+
+  /// Folds the metadata of an equivalent version into this one.
+  ///
+  /// Users that contributed to both are only counted once, in `total` and in `game_versions`.
+  pub fn merge(&mut self, mut other: Metadata) {
+    self.total += other.total;
+    self.canonical |= other.canonical;
+    self.first_seen = self.first_seen.min(other.first_seen);
+    self.last_seen = self.last_seen.max(other.last_seen);
+    for (user_id, score) in other.contributors {
+      match self.contributors.entry(user_id) {
+        Entry::Occupied(mut entry) => {
+          self.total = self.total.saturating_sub(1);
+          if let ScoreKey::Score(score) = score {
+            add_score(entry.get_mut(), score);
+          }
+
+          // Keep the user under this side's game version, or take the other side's if this one
+          // has none for them.
+          let user_id = entry.key();
+          if let Some(game_version) = other.contributor_game_versions.remove(user_id) {
+            if self.contributor_game_versions.contains_key(user_id) {
+              if let Some(count) = other.game_versions.get_mut(&game_version) {
+                *count = count.saturating_sub(1);
+              }
+            } else {
+              self
+                .contributor_game_versions
+                .insert(user_id.clone(), game_version);
+            }
+          }
+        }
+        Entry::Vacant(entry) => {
+          entry.insert(score);
+        }
+      }
+    }
+    for (user_id, seen) in other.contributor_last_seen {
+      let last_seen = self.contributor_last_seen.entry(user_id).or_insert(seen);
+      *last_seen = (*last_seen).max(seen);
+    }
+    self.aliases.extend(other.aliases);
+    self.history.merge(other.history);
+    self
+      .contributor_game_versions
+      .extend(other.contributor_game_versions);
+    for (game_version, count) in other.game_versions {
+      *self.game_versions.entry(game_version).or_default() += count;
+    }
+    // Users counted before their game versions were recorded cannot be matched up, so at least
+    // never count more of them on a game version than the version has.
+    let total = self.total;
+    self.game_versions.retain(|_, count| {
+      *count = (*count).min(total);
+      *count > 0
+    });
+  }
+}
+
+fn add_score(target: &mut ScoreKey, score: u32) {
+  match target {
+    ScoreKey::Score(current) => *current += score,
+    ScoreKey::ZeroKey(_) => *target = ScoreKey::Score(score),
+  }
+}
+
+/// Metadata holding a single anonymous submission made now.
+impl Default for Metadata {
+  fn default() -> Self {
+    let now = Utc::now();
+
+    let mut meta = Self::new(now);
+    meta.record(None, None, now);
+    meta
+  }
+}
+
+/// Publicly visible subset of [`Metadata`], omitting contributor information.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PublicMetadata {
+  pub total: u32,
+  pub canonical: bool,
+  pub first_seen: DateTime<Utc>,
+  pub last_seen: Option<DateTime<Utc>>,
+  pub aliases: BTreeSet<String>,
+  pub game_versions: BTreeMap<String, u32>,
+}
+
+impl From<Metadata> for PublicMetadata {
+  fn from(value: Metadata) -> Self {
+    Self {
+      total: value.total,
+      canonical: value.canonical,
+      first_seen: value.first_seen,
+      last_seen: value.last_seen,
+      aliases: value.aliases,
+      game_versions: value.game_versions,
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use chrono::Utc;
+
+  use super::{Metadata, Submission};
+
+  #[test]
+  fn test_record_counts_each_user_once() {
+    let now = Utc::now();
+    let mut meta = Metadata::new(now);
+
+    assert!(meta.record(Some("a"), Some("0.96a"), now));
+    assert!(!meta.record(Some("a"), Some("0.96a"), now));
+    assert!(meta.record(Some("b"), None, now));
+    assert!(meta.record(None, None, now));
+
+    assert_eq!(meta.total, 3);
+    assert_eq!(meta.game_versions.get("0.96a"), Some(&1));
+    assert_eq!(meta.history.daily.values().sum::<u32>(), 4);
+
+    let mut other = Metadata::new(now);
+    other.record(Some("a"), None, now);
+    other.record(Some("c"), None, now);
+    meta.merge(other);
+
+    assert_eq!(meta.total, 4);
+  }
+
+  #[test]
+  fn test_merge_counts_game_versions_once() {
+    let now = Utc::now();
+    let mut meta = Metadata::new(now);
+    meta.record(Some("a"), Some("0.96a"), now);
+    meta.record(Some("b"), None, now);
+
+    let mut other = Metadata::new(now);
+    other.record(Some("a"), Some("0.96a"), now);
+    other.record(Some("b"), Some("0.95.1a"), now);
+    other.record(Some("c"), Some("0.96a"), now);
+    meta.merge(other);
+
+    assert_eq!(meta.total, 3);
+    assert_eq!(meta.game_versions.get("0.96a"), Some(&2));
+    assert_eq!(meta.game_versions.get("0.95.1a"), Some(&1));
+  }
+
+  #[test]
+  fn test_submission_game_version() {
+    let parse = |game_version: &str| {
+      serde_json::from_value::<Submission>(serde_json::json!({
+        "game_version": game_version,
+        "mods": [],
+      }))
+      .unwrap()
+      .game_version
+    };
+
+    assert_eq!(parse("0.96a-RC10").as_deref(), Some("0.96a"));
+    assert_eq!(parse(" "), None);
+    assert_eq!(parse("*"), None);
+  }
 }
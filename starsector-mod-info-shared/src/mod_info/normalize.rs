@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+
+use super::{Metadata, ParsedVersion};
+
+/// Maps a raw version string onto the key its metadata is stored under.
+///
+/// Equivalent representations share a canonical key: `1.2`, `1.2.0`, `v1.2` and
+/// `{"major":1,"minor":2}` all become `1.2`. Trailing zero parts are dropped, but at least
+/// `major.minor` is always kept. Versions that cannot be parsed are used verbatim.
+pub fn canonical_key(raw: &str) -> String {
+  match ParsedVersion::parse(raw) {
+    Some(mut parsed) => {
+      while parsed.parts.len() > 2 && parsed.parts.last() == Some(&0) {
+        parsed.parts.pop();
+      }
+      parsed.parts.resize(parsed.parts.len().max(2), 0);
+
+      parsed.to_string()
+    }
+    None => raw.trim().to_owned(),
+  }
+}
+
+/// Maps a raw Starsector version onto the key installs are aggregated under.
+///
+/// Release candidates are folded into their release, so `0.96a-RC10` and `0.96a` share the key
+/// `0.96a`.
+pub fn game_version_key(raw: &str) -> String {
+  match ParsedVersion::parse(raw) {
+    Some(mut parsed) => {
+      parsed.pre_release = None;
+      parsed.to_string()
+    }
+    None => raw.trim().to_owned(),
+  }
+}
+
+/// Re-keys a stored version map on canonical keys, merging the metadata of equivalent versions.
+///
+/// Keys that change are kept as aliases of the version they were merged into. Returns whether
+/// any key was changed, so callers can skip rewriting blobs that are already normalized.
+pub fn normalize(map: HashMap<String, Metadata>) -> (HashMap<String, Metadata>, bool) {
+  let mut changed = false;
+  let mut normalized: HashMap<String, Metadata> = HashMap::with_capacity(map.len());
+
+  for (raw, mut meta) in map {
+    let key = canonical_key(&raw);
+    if key != raw {
+      changed = true;
+      meta.aliases.insert(raw);
+    }
+
+    if let Some(existing) = normalized.get_mut(&key) {
+      existing.merge(meta);
+    } else {
+      normalized.insert(key, meta);
+    }
+  }
+
+  (normalized, changed)
+}
+
+#[cfg(test)]
+mod test {
+  use std::collections::HashMap;
+
+  use super::{canonical_key, game_version_key, normalize};
+  use crate::mod_info::Metadata;
+
+  #[test]
+  fn test_canonical_key() {
+    assert_eq!(canonical_key("1.2"), "1.2");
+    assert_eq!(canonical_key("1.2.0"), "1.2");
+    assert_eq!(canonical_key("v1.2"), "1.2");
+    assert_eq!(canonical_key("1"), "1.0");
+    assert_eq!(canonical_key("1.0.0.0"), "1.0");
+    assert_eq!(canonical_key("1.2.0-RC1"), "1.2-rc1");
+    assert_eq!(canonical_key("1.2.3"), "1.2.3");
+    assert_eq!(canonical_key(" dev build "), "dev build");
+    assert_eq!(canonical_key("0.9.5-Hotfix"), "0.9.5-hotfix");
+    assert_eq!(canonical_key("1.2.3 build 4"), "1.2.3-build-4");
+  }
+
+  #[test]
+  fn test_canonical_key_collisions() {
+    let distinct = [
+      ("1.2.3-1", "1.2.31"),
+      ("1.2.3_2", "1.2.32"),
+      ("0.9.5a-hotfix", "0.9.5ahotfix"),
+      ("1.2.3-1-rc1", "1.2.3-rc1-2"),
+    ];
+    for (a, b) in distinct {
+      assert_ne!(canonical_key(a), canonical_key(b), "{} and {}", a, b);
+    }
+
+    // Keys are stable, so normalizing an already normalized map changes nothing.
+    for raw in [
+      "1.2.3-1",
+      "0.9.5a-hotfix",
+      "1.2.3 build 4",
+      "0.96a",
+      "1.2-rc1",
+    ] {
+      let key = canonical_key(raw);
+      assert_eq!(canonical_key(&key), key);
+    }
+  }
+
+  #[test]
+  fn test_game_version_key() {
+    assert_eq!(game_version_key("0.96a-RC10"), "0.96a");
+    assert_eq!(game_version_key("0.95.1a-RC6"), "0.95.1a");
+    assert_eq!(game_version_key("0.96a"), "0.96a");
+  }
+
+  #[test]
+  fn test_normalize_merges_equivalent_versions() {
+    let map = HashMap::from([
+      ("1.2".to_owned(), Metadata::default()),
+      ("1.2.0".to_owned(), Metadata::default()),
+      ("v1.2".to_owned(), Metadata::default()),
+    ]);
+
+    let (normalized, changed) = normalize(map);
+
+    assert!(changed);
+    assert_eq!(normalized.len(), 1);
+
+    let meta = &normalized["1.2"];
+    assert_eq!(meta.total, 3);
+    assert!(meta.aliases.contains("1.2.0") && meta.aliases.contains("v1.2"));
+  }
+}
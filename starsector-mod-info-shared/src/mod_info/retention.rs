@@ -0,0 +1,150 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{version, Metadata, StoredMod};
+
+/// Number of days a contributor is remembered after they last submitted a version. Users that
+/// return after this are counted as new installers again.
+pub const CONTRIBUTOR_RETENTION_DAYS: i64 = 365;
+/// Versions with at most this many installers are candidates for removal.
+pub const NEGLIGIBLE_TOTAL: u32 = 1;
+/// Number of days a negligible version is kept after it was last submitted.
+pub const VERSION_GRACE_DAYS: i64 = 90;
+/// Number of days a mod remembers the submissions applied to it. The broker gives up
+/// redelivering long before.
+pub const APPLIED_RETENTION_DAYS: i64 = 7;
+
+/// What compacting stored mods removed or rewrote.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, Debug, PartialEq)]
+pub struct Compaction {
+  pub histories_rolled_up: usize,
+  pub contributors_pruned: usize,
+  pub versions_dropped: usize,
+  #[serde(default)]
+  pub submissions_forgotten: usize,
+}
+
+impl Compaction {
+  pub fn changed(&self) -> bool {
+    *self != Compaction::default()
+  }
+
+  pub fn add(&mut self, other: Compaction) {
+    self.histories_rolled_up += other.histories_rolled_up;
+    self.contributors_pruned += other.contributors_pruned;
+    self.versions_dropped += other.versions_dropped;
+    self.submissions_forgotten += other.submissions_forgotten;
+  }
+}
+
+impl Metadata {
+  /// Forgets contributors that have not submitted this version since `cutoff`. Contributors
+  /// recorded before their submission times were tracked are dated by the version's last
+  /// submission. Returns how many were pruned.
+  pub fn prune_contributors(&mut self, cutoff: DateTime<Utc>) -> usize {
+    let fallback = self.last_seen.unwrap_or(self.first_seen);
+    let before = self.contributors.len();
+
+    let last_seen = &mut self.contributor_last_seen;
+    let game_versions = &mut self.contributor_game_versions;
+    self.contributors.retain(|user_id, _| {
+      let keep = *last_seen.get(user_id).unwrap_or(&fallback) >= cutoff;
+      if !keep {
+        last_seen.remove(user_id);
+        game_versions.remove(user_id);
+      }
+      keep
+    });
+
+    before - self.contributors.len()
+  }
+}
+
+impl StoredMod {
+  /// Forgets the submissions applied before `cutoff`. Returns how many were forgotten.
+  pub fn forget_applied(&mut self, cutoff: DateTime<Utc>) -> usize {
+    let before = self.applied.len();
+    self.applied.retain(|_, applied_at| *applied_at >= cutoff);
+
+    before - self.applied.len()
+  }
+
+  /// Rolls up every version's history, prunes inactive contributors, forgets old applied
+  /// submissions and drops versions that have had negligible installs for longer than the grace
+  /// period.
+  ///
+  /// The newest version and canonical versions are always kept, so a mod never loses all of its
+  /// versions.
+  pub fn compact(&mut self, now: DateTime<Utc>) -> Compaction {
+    let mut compaction = Compaction::default();
+
+    let contributor_cutoff = now - Duration::days(CONTRIBUTOR_RETENTION_DAYS);
+    for meta in self.versions.values_mut() {
+      if meta.history.roll_up(now) {
+        compaction.histories_rolled_up += 1;
+      }
+      compaction.contributors_pruned += meta.prune_contributors(contributor_cutoff);
+    }
+
+    let newest = self
+      .versions
+      .keys()
+      .max_by(|a, b| version::compare(a, b))
+      .cloned();
+    let grace_cutoff = now - Duration::days(VERSION_GRACE_DAYS);
+    let before = self.versions.len();
+    self.versions.retain(|version, meta| {
+      Some(version) == newest.as_ref()
+        || meta.canonical
+        || meta.total > NEGLIGIBLE_TOTAL
+        || meta.last_seen.unwrap_or(meta.first_seen) >= grace_cutoff
+    });
+    compaction.versions_dropped = before - self.versions.len();
+
+    compaction.submissions_forgotten =
+      self.forget_applied(now - Duration::days(APPLIED_RETENTION_DAYS));
+
+    compaction
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use chrono::{Duration, Utc};
+  use uuid::Uuid;
+
+  use crate::mod_info::{Metadata, StoredMod};
+
+  #[test]
+  fn test_compact() {
+    let now = Utc::now();
+    let long_ago = now - Duration::days(400);
+
+    let mut old = Metadata::new(long_ago);
+    old.record(Some("a"), None, long_ago);
+    let mut recent = Metadata::new(long_ago);
+    recent.record(Some("a"), None, long_ago);
+    recent.record(Some("b"), None, now);
+
+    let mut stored = StoredMod::default();
+    stored.mark_applied(Uuid::new_v4(), long_ago);
+    stored.mark_applied(Uuid::new_v4(), now);
+    stored.versions.insert("1.0".to_owned(), old);
+    stored.versions.insert("0.9".to_owned(), Metadata::new(long_ago));
+    stored.versions.insert("1.1".to_owned(), recent);
+
+    let compaction = stored.compact(now);
+
+    assert!(compaction.changed());
+    assert_eq!(compaction.versions_dropped, 2);
+    assert_eq!(compaction.contributors_pruned, 2);
+    assert_eq!(compaction.submissions_forgotten, 1);
+    assert_eq!(stored.applied.len(), 1);
+
+    let newest = &stored.versions["1.1"];
+    assert_eq!(newest.total, 2);
+    assert!(newest.contributors.contains_key("b") && !newest.contributors.contains_key("a"));
+
+    assert!(!stored.compact(now).changed());
+  }
+}
@@ -0,0 +1,186 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use super::{normalize::normalize, Metadata};
+
+/// Schema version written by this build.
+///
+/// Bump this whenever the stored shape of a mod changes, and add a function to [`MIGRATIONS`]
+/// that turns the previous version into the new one.
+pub const SCHEMA_VERSION: u32 = 3;
+
+/// Upgrades applied to a stored mod, in order. The function at index `i` turns schema `i + 1`
+/// into schema `i + 2`.
+///
+/// Migrations work on raw JSON rather than on [`Metadata`], so they keep working however much
+/// `Metadata` changes later.
+const MIGRATIONS: [fn(Value) -> Value; (SCHEMA_VERSION - 1) as usize] = [v1_to_v2, v2_to_v3];
+
+/// The stored form of a single mod, holding the metadata of each of its versions.
+///
+/// Deserializes from any schema version, upgrading older ones as it goes, and always serializes
+/// as [`SCHEMA_VERSION`].
+#[derive(Serialize, Deserialize)]
+#[serde(try_from = "Value")]
+pub struct StoredMod {
+  #[serde(serialize_with = "serialize_schema")]
+  schema: u32,
+  pub versions: HashMap<String, Metadata>,
+  /// Submissions recently applied to this mod, and when. Recorded in the same write as their
+  /// installs, so a redelivered submission is never counted twice.
+  pub applied: BTreeMap<Uuid, DateTime<Utc>>,
+  /// Whether any version was stored under a key [`canonical_key`](super::normalize::canonical_key)
+  /// no longer produces, and was re-keyed as it was read.
+  #[serde(skip)]
+  rekeyed: bool,
+}
+
+impl StoredMod {
+  /// Schema version the object was stored as, before it was upgraded.
+  pub fn stored_schema(&self) -> u32 {
+    self.schema
+  }
+
+  /// Whether the object was stored under an older schema or older version keys, and should be
+  /// rewritten.
+  pub fn is_outdated(&self) -> bool {
+    self.schema < SCHEMA_VERSION || self.rekeyed
+  }
+
+  /// Whether submission `id` has already been applied to this mod.
+  pub fn has_applied(&self, id: &Uuid) -> bool {
+    self.applied.contains_key(id)
+  }
+
+  /// Records that submission `id` was applied to this mod at `now`.
+  pub fn mark_applied(&mut self, id: Uuid, now: DateTime<Utc>) {
+    self.applied.insert(id, now);
+  }
+}
+
+impl Default for StoredMod {
+  fn default() -> Self {
+    Self {
+      schema: SCHEMA_VERSION,
+      versions: HashMap::new(),
+      applied: BTreeMap::new(),
+      rekeyed: false,
+    }
+  }
+}
+
+impl TryFrom<Value> for StoredMod {
+  type Error = String;
+
+  fn try_from(value: Value) -> Result<Self, Self::Error> {
+    let schema = schema_of(&value);
+    if schema > SCHEMA_VERSION {
+      return Err(format!(
+        "Stored schema {} is newer than the supported schema {}",
+        schema, SCHEMA_VERSION
+      ));
+    }
+
+    let value = MIGRATIONS[(schema - 1) as usize..]
+      .iter()
+      .fold(value, |value, migrate| migrate(value));
+
+    let versions: HashMap<String, Metadata> =
+      serde_json::from_value(value["versions"].clone()).map_err(|err| err.to_string())?;
+
+    // Schema 1 predates canonical version keys, and keys written since may have been derived by
+    // an older `canonical_key`, so they are re-keyed on every read rather than by a migration.
+    let (versions, rekeyed) = normalize(versions);
+
+    let applied =
+      serde_json::from_value(value["applied"].clone()).map_err(|err| err.to_string())?;
+
+    Ok(Self {
+      schema,
+      versions,
+      applied,
+      rekeyed,
+    })
+  }
+}
+
+/// Schema 1 objects are a bare map of version to metadata, without an envelope.
+fn schema_of(value: &Value) -> u32 {
+  value
+    .get("schema")
+    .and_then(Value::as_u64)
+    .and_then(|schema| u32::try_from(schema).ok())
+    .filter(|schema| *schema > 0 && value.get("versions").is_some())
+    .unwrap_or(1)
+}
+
+fn v1_to_v2(value: Value) -> Value {
+  json!({ "schema": 2, "versions": value })
+}
+
+fn v2_to_v3(mut value: Value) -> Value {
+  value["schema"] = json!(3);
+  value["applied"] = json!({});
+  value
+}
+
+fn serialize_schema<S: serde::Serializer>(_: &u32, serializer: S) -> Result<S::Ok, S::Error> {
+  serializer.serialize_u32(SCHEMA_VERSION)
+}
+
+#[cfg(test)]
+mod test {
+  use serde_json::json;
+
+  use super::{StoredMod, SCHEMA_VERSION};
+
+  #[test]
+  fn test_upgrades_bare_map() {
+    let meta = json!({
+      "total": 2,
+      "canonical": false,
+      "first_seen": "2023-01-01T00:00:00Z",
+      "contributors": {},
+    });
+    let stored: StoredMod = serde_json::from_value(json!({ "1.2.0": meta, "1.2": meta })).unwrap();
+
+    assert!(stored.is_outdated());
+    assert_eq!(stored.versions.len(), 1);
+    assert_eq!(stored.versions["1.2"].total, 4);
+
+    let written = serde_json::to_value(&stored).unwrap();
+    assert_eq!(written["schema"], SCHEMA_VERSION);
+
+    let reread: StoredMod = serde_json::from_value(written).unwrap();
+    assert!(!reread.is_outdated());
+    assert_eq!(reread.versions["1.2"].total, 4);
+    assert!(reread.applied.is_empty());
+  }
+
+  #[test]
+  fn test_rekeys_current_schema() {
+    let meta = json!({
+      "total": 1,
+      "canonical": false,
+      "first_seen": "2023-01-01T00:00:00Z",
+      "contributors": {},
+    });
+    let stored: StoredMod = serde_json::from_value(json!({
+      "schema": SCHEMA_VERSION,
+      "versions": { "1.2.3build4": meta, "1.2.3-build-4": meta },
+      "applied": {},
+    }))
+    .unwrap();
+
+    assert!(stored.is_outdated());
+    assert_eq!(stored.versions.len(), 1);
+    assert_eq!(stored.versions["1.2.3-build-4"].total, 2);
+
+    let reread: StoredMod = serde_json::from_value(serde_json::to_value(&stored).unwrap()).unwrap();
+    assert!(!reread.is_outdated());
+  }
+}
@@ -0,0 +1,232 @@
+use std::{cmp::Ordering, fmt::Display};
+
+/// Characters that separate the words of a version's tail.
+const SEPARATORS: [char; 5] = ['-', '_', '+', '.', ' '];
+
+/// Structured form of a mod version string, ordered the way mod authors intend.
+///
+/// Handles the formats commonly seen in Starsector mods: dotted numeric parts (`1.2.3`), an
+/// optional missing patch (`1.2`), a leading `v`, letter suffixes (`0.9.5a`) and pre-release
+/// tags (`1.2.0-RC2`, `1.2-beta`). Missing numeric parts are treated as zero, so `1.2` and
+/// `1.2.0` compare as equal.
+#[derive(Clone, Debug)]
+pub struct ParsedVersion {
+  pub parts: Vec<u32>,
+  pub suffix: String,
+  pub pre_release: Option<PreRelease>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PreRelease {
+  pub kind: PreReleaseKind,
+  pub number: Option<u32>,
+}
+
+/// Pre-release tags, from least to most mature.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PreReleaseKind {
+  Dev,
+  Alpha,
+  Beta,
+  Rc,
+}
+
+impl PreReleaseKind {
+  fn from_tag(tag: &str) -> Option<Self> {
+    match tag {
+      "dev" | "snapshot" | "nightly" => Some(Self::Dev),
+      "alpha" => Some(Self::Alpha),
+      "beta" => Some(Self::Beta),
+      "rc" | "pre" | "preview" => Some(Self::Rc),
+      _ => None,
+    }
+  }
+
+  fn as_str(&self) -> &'static str {
+    match self {
+      Self::Dev => "dev",
+      Self::Alpha => "alpha",
+      Self::Beta => "beta",
+      Self::Rc => "rc",
+    }
+  }
+}
+
+impl ParsedVersion {
+  /// Parses a version string, returning `None` if it does not start with a numeric part.
+  pub fn parse(raw: &str) -> Option<Self> {
+    let lower = raw.trim().to_lowercase();
+    let trimmed = lower.strip_prefix('v').unwrap_or(&lower).trim_start();
+
+    let end = trimmed
+      .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+      .unwrap_or(trimmed.len());
+    let (numeric, mut tail) = trimmed.split_at(end);
+
+    let parts = numeric
+      .split('.')
+      .filter(|part| !part.is_empty())
+      .map(|part| part.parse::<u32>().ok())
+      .collect::<Option<Vec<_>>>()?;
+    if parts.is_empty() {
+      return None;
+    }
+
+    let mut suffix = String::new();
+    let mut pre_release = None;
+    loop {
+      tail = tail.trim_start_matches(SEPARATORS);
+      if tail.is_empty() {
+        break;
+      }
+
+      // Words are runs of letters, or runs of anything else up to the next letter or separator.
+      let alphabetic = tail.starts_with(|c: char| c.is_ascii_alphabetic());
+      let word_end = tail
+        .find(|c: char| c.is_ascii_alphabetic() != alphabetic || SEPARATORS.contains(&c))
+        .unwrap_or(tail.len());
+      let (word, rest) = tail.split_at(word_end);
+
+      if let (None, Some(kind)) = (&pre_release, PreReleaseKind::from_tag(word)) {
+        let rest = rest.trim_start_matches(['-', '_', '.', ' ']);
+        let digits_end = rest
+          .find(|c: char| !c.is_ascii_digit())
+          .unwrap_or(rest.len());
+        let (digits, rest) = rest.split_at(digits_end);
+
+        pre_release = Some(PreRelease {
+          kind,
+          number: digits.parse().ok(),
+        });
+        tail = rest;
+      } else {
+        // Anything that is not a recognised pre-release tag is kept as a suffix, so that
+        // `0.9.5a` and `0.9.5-hotfix` still sort after `0.9.5`. Its words are kept apart, so
+        // `0.9.5a-hotfix` and `0.9.5ahotfix` stay distinct.
+        if !suffix.is_empty() {
+          suffix.push('-');
+        }
+        suffix.push_str(word);
+        tail = rest;
+      }
+    }
+
+    Some(ParsedVersion {
+      parts,
+      suffix,
+      pre_release,
+    })
+  }
+
+  fn part(&self, idx: usize) -> u32 {
+    self.parts.get(idx).copied().unwrap_or_default()
+  }
+}
+
+impl Display for ParsedVersion {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let parts: Vec<String> = self.parts.iter().map(u32::to_string).collect();
+    write!(f, "{}", parts.join("."))?;
+
+    // Only a single letter, as in `0.9.5a`, joins the numeric parts directly. Anything else is
+    // set apart, so that `1.2.3-1` cannot read as `1.2.31`.
+    let mut chars = self.suffix.chars();
+    let letter = matches!(
+      (chars.next(), chars.next()),
+      (Some(c), None | Some('-')) if c.is_ascii_alphabetic()
+    );
+    if !self.suffix.is_empty() && !letter {
+      write!(f, "-")?;
+    }
+    write!(f, "{}", self.suffix)?;
+
+    if let Some(pre_release) = &self.pre_release {
+      write!(f, "-{}", pre_release.kind.as_str())?;
+      if let Some(number) = pre_release.number {
+        write!(f, "{}", number)?;
+      }
+    }
+
+    Ok(())
+  }
+}
+
+impl PartialEq for ParsedVersion {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other) == Ordering::Equal
+  }
+}
+
+impl Eq for ParsedVersion {}
+
+impl PartialOrd for ParsedVersion {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for ParsedVersion {
+  fn cmp(&self, other: &Self) -> Ordering {
+    let len = self.parts.len().max(other.parts.len());
+
+    (0..len)
+      .map(|idx| self.part(idx).cmp(&other.part(idx)))
+      .find(|ord| ord.is_ne())
+      .unwrap_or(Ordering::Equal)
+      .then_with(|| self.suffix.cmp(&other.suffix))
+      .then_with(|| match (&self.pre_release, &other.pre_release) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+        (Some(this), Some(other)) => this.cmp(other),
+      })
+  }
+}
+
+/// Compares two raw version strings by their parsed form. Versions that cannot be parsed order
+/// before those that can, and amongst themselves by their raw text.
+pub fn compare(a: &str, b: &str) -> Ordering {
+  match (ParsedVersion::parse(a), ParsedVersion::parse(b)) {
+    (Some(a), Some(b)) => a.cmp(&b),
+    (Some(_), None) => Ordering::Greater,
+    (None, Some(_)) => Ordering::Less,
+    (None, None) => a.cmp(b),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::cmp::Ordering;
+
+  use super::{compare, ParsedVersion};
+
+  fn parse(raw: &str) -> ParsedVersion {
+    ParsedVersion::parse(raw).expect(raw)
+  }
+
+  #[test]
+  fn test_version_ordering() {
+    assert!(parse("0.9.5a") > parse("0.9.5"));
+    assert!(parse("0.9.5b") > parse("0.9.5a"));
+    assert!(parse("1.10.0") > parse("1.9.3"));
+    assert!(parse("1.2.0-rc2") > parse("1.2.0-rc1"));
+    assert!(parse("1.2.0-RC1") > parse("1.2.0-beta"));
+    assert!(parse("1.2.0") > parse("1.2.0-rc2"));
+    assert!(parse("1.2.1-alpha") > parse("1.2.0"));
+  }
+
+  #[test]
+  fn test_version_equivalence() {
+    assert_eq!(parse("1.2"), parse("1.2.0"));
+    assert_eq!(parse("v1.2.3"), parse("1.2.3"));
+    assert_eq!(parse("1.2.3-RC2"), parse("1.2.3 rc 2"));
+    assert_eq!(parse("V1.2-Beta").to_string(), "1.2-beta");
+  }
+
+  #[test]
+  fn test_unparseable_version() {
+    assert!(ParsedVersion::parse("beta").is_none());
+    assert!(ParsedVersion::parse("").is_none());
+    assert_eq!(compare("beta", "0.1"), Ordering::Less);
+  }
+}
@@ -0,0 +1,76 @@
+use serde_json::Value;
+use worker::{
+  async_trait::async_trait, wasm_bindgen::JsValue, Env, Fetch, Headers, Method, Request,
+  RequestInit,
+};
+
+use super::Publisher;
+use crate::amqp::{HTTPAmqp, Properties};
+
+const DEFAULT_HOST: &str = "moose.rmq.cloudamqp.com";
+
+/// Publishes through the RabbitMQ HTTP API of a CloudAMQP instance, to the default exchange of
+/// the vhost named after the user.
+///
+/// Configured by the `AMQP_HOST` and `AMQP_USERNAME` variables and the `AMQP_KEY` secret.
+pub struct CloudAmqpPublisher {
+  host: String,
+  username: String,
+  key: String,
+}
+
+impl CloudAmqpPublisher {
+  pub fn from_env(env: &Env) -> worker::Result<Self> {
+    Ok(Self {
+      host: env
+        .var("AMQP_HOST")
+        .map(|host| host.to_string())
+        .unwrap_or_else(|_| DEFAULT_HOST.to_owned()),
+      username: env.var("AMQP_USERNAME")?.to_string(),
+      key: env.secret("AMQP_KEY")?.to_string(),
+    })
+  }
+}
+
+#[async_trait(?Send)]
+impl Publisher for CloudAmqpPublisher {
+  async fn publish(
+    &self,
+    routing_key: &str,
+    payload: String,
+    properties: Properties,
+  ) -> worker::Result<bool> {
+    let http_amqp: String = HTTPAmqp::from_json(routing_key, &payload)
+      .with_properties(properties)
+      .try_into()?;
+
+    let mut headers = Headers::new();
+    headers.append("Content-Type", "application/json")?;
+    let credentials = base64::encode(format!("{}:{}", self.username, self.key));
+    headers.append("Authorization", &format!("Basic {}", credentials))?;
+
+    let request = Request::new_with_init(
+      &format!(
+        "https://{}/api/exchanges/{}/amq.default/publish",
+        self.host, self.username
+      ),
+      RequestInit::new()
+        .with_method(Method::Post)
+        .with_headers(headers)
+        .with_body(Some(JsValue::from_str(&http_amqp))),
+    )?;
+
+    let routed = Fetch::Request(request)
+      .send()
+      .await?
+      .json::<Value>()
+      .await?;
+
+    Ok(
+      routed
+        .get("routed")
+        .and_then(|routed| routed.as_bool())
+        .unwrap_or_default(),
+    )
+  }
+}
@@ -0,0 +1,223 @@
+use worker::{async_trait::async_trait, console_error, Env};
+
+use crate::{
+  amqp::{Properties, SCHEMA_VERSION_HEADER},
+  message::{self, Envelope, Message, MESSAGE_SCHEMA_VERSION, ROUTING_KEY},
+  mod_info::Submission,
+};
+
+pub mod cloudamqp;
+pub mod outbox;
+pub mod queue;
+pub mod retry;
+
+pub use cloudamqp::CloudAmqpPublisher;
+pub use outbox::{Outbox, OutboxPublisher};
+pub use queue::QueuePublisher;
+pub use retry::RetryPublisher;
+
+/// Hands messages from the edge to whatever delivers them to `starsector-mod-info-storage`.
+#[async_trait(?Send)]
+pub trait Publisher {
+  /// Publishes a JSON `payload` under `routing_key` with the given message `properties`. Returns
+  /// whether the backend accepted it.
+  async fn publish(
+    &self,
+    routing_key: &str,
+    payload: String,
+    properties: Properties,
+  ) -> worker::Result<bool>;
+
+  /// Publishes `message` in an [`Envelope`] to the storage worker, typed by its kind.
+  async fn send(&self, message: Message, properties: Properties) -> worker::Result<bool> {
+    let properties = properties
+      .kind(message.kind())
+      .header(SCHEMA_VERSION_HEADER, MESSAGE_SCHEMA_VERSION);
+    let payload = serde_json::to_string(&Envelope::new(message))?;
+
+    self.publish(ROUTING_KEY, payload, properties).await
+  }
+}
+
+/// Builds the publisher named by the `PUBLISHER` variable: `cloudamqp` (the default) or `queue`.
+/// See each publisher for the rest of its configuration.
+pub fn from_env(env: &Env) -> worker::Result<Box<dyn Publisher>> {
+  let backend = env
+    .var("PUBLISHER")
+    .map(|backend| backend.to_string())
+    .unwrap_or_else(|_| "cloudamqp".to_owned());
+
+  match backend.as_str() {
+    "cloudamqp" => Ok(Box::new(CloudAmqpPublisher::from_env(env)?)),
+    "queue" => Ok(Box::new(QueuePublisher::from_env(env)?)),
+    _ => Err(worker::Error::RustError(format!(
+      "Unknown publisher: {}",
+      backend
+    ))),
+  }
+}
+
+/// Like [`from_env`], but retries failed publishes with backoff, then buffers whatever still
+/// fails in the [`Outbox`] bound as `PUBLISH_OUTBOX`.
+pub fn reliable_from_env(env: &Env) -> worker::Result<Box<dyn Publisher>> {
+  Ok(Box::new(OutboxPublisher::new(
+    Box::new(RetryPublisher::new(from_env(env)?)),
+    Outbox::from_env(env)?,
+  )))
+}
+
+/// Publishes a client's `submission` through `publisher`, with its id as the message id so
+/// redeliveries can be recognised.
+pub async fn send_submission(
+  publisher: &dyn Publisher,
+  submission: Submission,
+) -> worker::Result<bool> {
+  let mut properties = message::properties();
+  if let Some(id) = submission.id {
+    properties = properties.message_id(id.to_string());
+  }
+
+  publisher
+    .send(Message::InstalledMods(submission), properties)
+    .await
+}
+
+/// Sends `message` through the publisher configured in `env`, on behalf of work that has already
+/// succeeded on its own. Failures are only logged.
+pub async fn notify(env: &Env, message: Message) {
+  let kind = message.kind();
+  let sent = match from_env(env) {
+    Ok(publisher) => publisher.send(message, message::properties()).await,
+    Err(err) => Err(err),
+  };
+
+  match sent {
+    Ok(true) => {}
+    Ok(false) => console_error!("Message of kind {} was not routed", kind),
+    Err(err) => console_error!("Failed to publish message of kind {}: {}", kind, err),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::cell::RefCell;
+
+  use futures_util::FutureExt;
+  use worker::async_trait::async_trait;
+
+  use super::{send_submission, Publisher};
+  use crate::{
+    amqp::{DeliveryMode, Properties},
+    message::{self, Envelope, Message, UserCreated, MESSAGE_SCHEMA_VERSION, ROUTING_KEY},
+    mod_info::Submission,
+  };
+
+  /// Keeps published messages in memory.
+  #[derive(Default)]
+  struct MemoryPublisher {
+    messages: RefCell<Vec<Published>>,
+  }
+
+  impl MemoryPublisher {
+    /// Everything published so far, in order.
+    fn messages(&self) -> Vec<Published> {
+      self.messages.borrow().clone()
+    }
+  }
+
+  /// A message recorded by [`MemoryPublisher`].
+  #[derive(Clone, Debug, PartialEq)]
+  struct Published {
+    routing_key: String,
+    payload: String,
+    properties: Properties,
+  }
+
+  impl Published {
+    fn envelope(&self) -> Envelope {
+      serde_json::from_str(&self.payload).unwrap()
+    }
+  }
+
+  #[async_trait(?Send)]
+  impl Publisher for MemoryPublisher {
+    async fn publish(
+      &self,
+      routing_key: &str,
+      payload: String,
+      properties: Properties,
+    ) -> worker::Result<bool> {
+      self.messages.borrow_mut().push(Published {
+        routing_key: routing_key.to_owned(),
+        payload,
+        properties,
+      });
+
+      Ok(true)
+    }
+  }
+
+  #[test]
+  fn test_send_wraps_message_in_envelope() {
+    let publisher = MemoryPublisher::default();
+    let created = UserCreated::new("abc".to_owned());
+
+    let sent = publisher
+      .send(Message::UserCreated(created.clone()), message::properties())
+      .now_or_never()
+      .unwrap()
+      .unwrap();
+    assert!(sent);
+
+    let messages = publisher.messages();
+    let [published] = &messages[..] else {
+      panic!("expected a single message");
+    };
+    assert_eq!(published.routing_key, ROUTING_KEY);
+    assert_eq!(published.properties.kind.as_deref(), Some("user_created"));
+    assert_eq!(
+      published.properties.schema_version(),
+      Some(u64::from(MESSAGE_SCHEMA_VERSION))
+    );
+    assert_eq!(
+      published.properties.delivery_mode,
+      Some(DeliveryMode::Persistent)
+    );
+
+    let envelope = published.envelope();
+    assert_eq!(envelope.version, MESSAGE_SCHEMA_VERSION);
+    assert!(matches!(envelope.message, Message::UserCreated(user) if user == created));
+  }
+
+  #[test]
+  fn test_send_submission() {
+    let publisher = MemoryPublisher::default();
+    let submission: Submission = serde_json::from_value(serde_json::json!({
+      "game_version": "0.96a-RC10",
+      "mods": [{ "id": "lw_lazylib", "version": "2.8" }],
+    }))
+    .unwrap();
+    let submission = submission.with_new_id().with_user_id("abc".to_owned());
+    let id = submission.id.unwrap();
+
+    send_submission(&publisher, submission)
+      .now_or_never()
+      .unwrap()
+      .unwrap();
+
+    let messages = publisher.messages();
+    let [published] = &messages[..] else {
+      panic!("expected a single message");
+    };
+    assert_eq!(published.properties.kind.as_deref(), Some("installed_mods"));
+    assert_eq!(published.properties.message_id, Some(id.to_string()));
+
+    let Message::InstalledMods(submission) = published.envelope().message else {
+      panic!("expected installed mods");
+    };
+    assert_eq!(submission.id, Some(id));
+    assert_eq!(submission.user_id.as_deref(), Some("abc"));
+    assert_eq!(submission.game_version.as_deref(), Some("0.96a"));
+    assert_eq!(submission.mods[0].id, "lw_lazylib");
+  }
+}
@@ -0,0 +1,356 @@
+use std::{ops::Deref, time::Duration};
+
+use chrono::{DateTime, Utc};
+use rand::{rngs::StdRng, SeedableRng};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use worker::{
+  async_trait::async_trait, console_error, wasm_bindgen::JsValue, Env, Method, Request,
+  RequestInit, Response, State, Stub,
+};
+
+use super::{from_env, retry::backoff, Publisher};
+use crate::{amqp::Properties, assert_method, durable::*, route_from_req};
+
+/// Binding of the outbox's Durable Object namespace.
+const OUTBOX_BINDING: &str = "PUBLISH_OUTBOX";
+const OUTBOX_ID: &str = "outbox";
+
+const HEAD_KEY: &str = "head";
+const TAIL_KEY: &str = "tail";
+const FAILURES_KEY: &str = "failures";
+const SET_ASIDE_KEY: &str = "set_aside";
+
+/// Most messages the outbox holds. Beyond this, buffering fails just as publishing did.
+pub const OUTBOX_CAPACITY: u64 = 10_000;
+
+/// Most messages published by a single drain.
+const DRAIN_BATCH: u64 = 25;
+
+/// Drains in which the broker may refuse to route a message before it is set aside, so that the
+/// messages behind it are not held up forever.
+const MAX_ROUTING_ATTEMPTS: u32 = 5;
+
+/// Delay before a drain, on top of any backoff from earlier failed drains.
+const DRAIN_DELAY: Duration = Duration::from_secs(10);
+const DRAIN_BACKOFF_BASE: Duration = Duration::from_secs(30);
+const DRAIN_BACKOFF_MAX: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Clone, Debug, PartialEq, strum::IntoStaticStr, strum::EnumString)]
+#[strum(serialize_all = "snake_case")]
+enum OutboxRoutes {
+  Push,
+  Depth,
+  #[strum(default)]
+  Unknown(String),
+}
+
+impl Deref for OutboxRoutes {
+  type Target = str;
+
+  fn deref(&self) -> &Self::Target {
+    self.into()
+  }
+}
+
+impl From<&OutboxRoutes> for Method {
+  fn from(value: &OutboxRoutes) -> Self {
+    match value {
+      OutboxRoutes::Push => Method::Put,
+      OutboxRoutes::Depth => Method::Get,
+      OutboxRoutes::Unknown(_) => Method::Get,
+    }
+  }
+}
+
+impl From<OutboxRoutes> for Method {
+  fn from(value: OutboxRoutes) -> Self {
+    value.into()
+  }
+}
+
+/// A message that could not be published, waiting in the outbox.
+#[derive(Serialize, Deserialize)]
+pub struct Buffered {
+  pub routing_key: String,
+  pub payload: String,
+  pub properties: Properties,
+  pub buffered_at: DateTime<Utc>,
+  /// Drains in which the broker accepted the message but did not route it.
+  #[serde(default)]
+  pub attempts: u32,
+}
+
+/// How far behind the outbox is.
+#[derive(Serialize, Deserialize)]
+pub struct Depth {
+  pub depth: u64,
+  pub capacity: u64,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub oldest_buffered_at: Option<DateTime<Utc>>,
+  /// Drains that have failed in a row, which back off the next one.
+  pub failed_drains: u32,
+  /// Messages set aside after the broker refused to route them [`MAX_ROUTING_ATTEMPTS`] times.
+  #[serde(default)]
+  pub set_aside: u64,
+}
+
+/// Buffers messages in order, and publishes them again from an alarm until the broker takes them.
+///
+/// Messages are stored under consecutive sequence numbers, from `head` up to but excluding
+/// `tail`. Those the broker keeps refusing to route are moved to `unroutable/<sequence>` for
+/// inspection, rather than retried forever.
+#[durable_object]
+pub struct DurableOutbox {
+  state: State,
+  env: Env,
+}
+
+#[durable_object]
+impl DurableObject for DurableOutbox {
+  fn new(state: State, env: Env) -> Self {
+    Self { state, env }
+  }
+
+  async fn fetch(&mut self, mut req: Request) -> worker::Result<Response> {
+    match route_from_req(&req)? {
+      OutboxRoutes::Push => {
+        assert_method!(req, OutboxRoutes::Push.into());
+
+        let buffered: Buffered = req.json().await?;
+        let (head, tail) = self.bounds().await?;
+        if tail - head >= OUTBOX_CAPACITY {
+          return Response::error("Outbox is full", 507);
+        }
+
+        self
+          .state
+          .storage()
+          .put(&message_key(tail), serde_json::to_string(&buffered)?)
+          .await?;
+        self.state.storage().put(TAIL_KEY, tail + 1).await?;
+
+        // Drains reschedule themselves while messages remain, but one that failed before doing
+        // so leaves none scheduled, so check rather than trusting the outbox was empty.
+        if self.state.storage().get_alarm().await?.is_none() {
+          self.state.storage().set_alarm(DRAIN_DELAY).await?;
+        }
+
+        Response::empty()
+      }
+      OutboxRoutes::Depth => Response::from_json(&self.depth().await?),
+      OutboxRoutes::Unknown(path) => Response::error(format!("Could not find path: {}", path), 404),
+    }
+  }
+
+  async fn alarm(&mut self) -> worker::Result<Response> {
+    let failures = match self.drain().await {
+      Ok(()) => 0,
+      Err(err) => {
+        console_error!("Draining the outbox failed: {}", err);
+        self.get_or(FAILURES_KEY, 0u32).await? + 1
+      }
+    };
+    self.state.storage().put(FAILURES_KEY, failures).await?;
+
+    let (head, tail) = self.bounds().await?;
+    if head < tail {
+      let delay = if failures == 0 {
+        DRAIN_DELAY
+      } else {
+        let mut rng = StdRng::from_entropy();
+        DRAIN_DELAY + backoff(DRAIN_BACKOFF_BASE, DRAIN_BACKOFF_MAX, failures, &mut rng)
+      };
+      self.state.storage().set_alarm(delay).await?;
+    }
+
+    Response::empty()
+  }
+}
+
+impl DurableOutbox {
+  /// Publishes up to [`DRAIN_BATCH`] messages in order, stopping at the first that fails.
+  ///
+  /// A message the broker does not route counts an attempt against it, and once it runs out of
+  /// attempts it is set aside so draining can move on. Errors reaching the broker at all count
+  /// against no message.
+  async fn drain(&self) -> worker::Result<()> {
+    let publisher = from_env(&self.env)?;
+    let (mut head, tail) = self.bounds().await?;
+    let end = tail.min(head + DRAIN_BATCH);
+
+    while head < end {
+      let key = message_key(head);
+      let raw: String = self.state.storage().get(&key).await?;
+      let mut buffered: Buffered = serde_json::from_str(&raw)?;
+
+      let published = publisher
+        .publish(
+          &buffered.routing_key,
+          buffered.payload.clone(),
+          buffered.properties.clone(),
+        )
+        .await?;
+      if !published {
+        buffered.attempts += 1;
+        if buffered.attempts < MAX_ROUTING_ATTEMPTS {
+          self
+            .state
+            .storage()
+            .put(&key, serde_json::to_string(&buffered)?)
+            .await?;
+          return Err(worker::Error::RustError(format!(
+            "Message {} was not routed",
+            head
+          )));
+        }
+
+        console_error!(
+          "Message {} was not routed after {} attempts, setting it aside",
+          head,
+          buffered.attempts
+        );
+        self
+          .state
+          .storage()
+          .put(&unroutable_key(head), serde_json::to_string(&buffered)?)
+          .await?;
+        let set_aside: u64 = self.get_or(SET_ASIDE_KEY, 0).await?;
+        self
+          .state
+          .storage()
+          .put(SET_ASIDE_KEY, set_aside + 1)
+          .await?;
+      }
+
+      head += 1;
+      self.state.storage().put(HEAD_KEY, head).await?;
+      self.state.storage().delete(&key).await?;
+    }
+
+    Ok(())
+  }
+
+  async fn depth(&self) -> worker::Result<Depth> {
+    let (head, tail) = self.bounds().await?;
+
+    let oldest_buffered_at = if head < tail {
+      let buffered: String = self.state.storage().get(&message_key(head)).await?;
+      Some(serde_json::from_str::<Buffered>(&buffered)?.buffered_at)
+    } else {
+      None
+    };
+
+    Ok(Depth {
+      depth: tail - head,
+      capacity: OUTBOX_CAPACITY,
+      oldest_buffered_at,
+      failed_drains: self.get_or(FAILURES_KEY, 0).await?,
+      set_aside: self.get_or(SET_ASIDE_KEY, 0).await?,
+    })
+  }
+
+  async fn bounds(&self) -> worker::Result<(u64, u64)> {
+    Ok((
+      self.get_or(HEAD_KEY, 0).await?,
+      self.get_or(TAIL_KEY, 0).await?,
+    ))
+  }
+
+  async fn get_or<T: DeserializeOwned>(&self, key: &str, default: T) -> worker::Result<T> {
+    match self.state.storage().get(key).await {
+      Err(worker::Error::JsError(val)) if val == "No such value in storage." => Ok(default),
+      value => value,
+    }
+  }
+}
+
+fn message_key(sequence: u64) -> String {
+  format!("message/{:020}", sequence)
+}
+
+fn unroutable_key(sequence: u64) -> String {
+  format!("unroutable/{:020}", sequence)
+}
+
+pub struct Outbox(Stub);
+
+impl Outbox {
+  pub fn from_env(env: &Env) -> worker::Result<Self> {
+    let namespace = env.durable_object(OUTBOX_BINDING)?;
+
+    let id = namespace.id_from_name(OUTBOX_ID)?;
+
+    id.get_stub().map(Self)
+  }
+
+  /// Buffers a message. Returns whether there was room for it.
+  pub async fn push(&self, buffered: &Buffered) -> worker::Result<bool> {
+    let res = self
+      .0
+      .fetch_with_request(Request::new_with_init(
+        &OutboxRoutes::Push,
+        RequestInit::new()
+          .with_method(OutboxRoutes::Push.into())
+          .with_body(Some(JsValue::from_str(&serde_json::to_string(buffered)?))),
+      )?)
+      .await?;
+
+    Ok(res.status_code() == 200)
+  }
+
+  pub async fn depth(&self) -> worker::Result<Depth> {
+    self
+      .0
+      .fetch_with_str(&OutboxRoutes::Depth)
+      .await?
+      .json()
+      .await
+  }
+}
+
+/// Publishes through another publisher, buffering whatever it fails to publish in the [`Outbox`]
+/// to be published later. Buffered messages count as accepted.
+pub struct OutboxPublisher {
+  inner: Box<dyn Publisher>,
+  outbox: Outbox,
+}
+
+impl OutboxPublisher {
+  pub fn new(inner: Box<dyn Publisher>, outbox: Outbox) -> Self {
+    Self { inner, outbox }
+  }
+}
+
+#[async_trait(?Send)]
+impl Publisher for OutboxPublisher {
+  async fn publish(
+    &self,
+    routing_key: &str,
+    payload: String,
+    properties: Properties,
+  ) -> worker::Result<bool> {
+    match self
+      .inner
+      .publish(routing_key, payload.clone(), properties.clone())
+      .await
+    {
+      Ok(true) => return Ok(true),
+      Ok(false) => console_error!("Message was not routed, buffering it in the outbox"),
+      Err(err) => console_error!(
+        "Publishing failed, buffering the message in the outbox: {}",
+        err
+      ),
+    }
+
+    self
+      .outbox
+      .push(&Buffered {
+        routing_key: routing_key.to_owned(),
+        payload,
+        properties,
+        buffered_at: Utc::now(),
+        attempts: 0,
+      })
+      .await
+  }
+}
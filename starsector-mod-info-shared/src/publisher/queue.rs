@@ -0,0 +1,42 @@
+use worker::{async_trait::async_trait, Env, Queue};
+
+use super::Publisher;
+use crate::amqp::Properties;
+
+/// Binding of the queue, which the worker's `wrangler.toml` has to declare as a producer.
+const QUEUE_BINDING: &str = "SUBMISSIONS_QUEUE";
+
+/// Publishes to a Cloudflare Queue bound as [`QUEUE_BINDING`], consumed by the `queue` handler
+/// of `starsector-mod-info-storage`.
+///
+/// Queues have no routing or message properties, so messages are sent as their bare payload.
+pub struct QueuePublisher {
+  queue: Queue,
+}
+
+impl QueuePublisher {
+  pub fn from_env(env: &Env) -> worker::Result<Self> {
+    let queue = env.queue(QUEUE_BINDING).map_err(|err| {
+      worker::Error::RustError(format!(
+        "PUBLISHER is \"queue\", but no queue is bound as {}: {}",
+        QUEUE_BINDING, err
+      ))
+    })?;
+
+    Ok(Self { queue })
+  }
+}
+
+#[async_trait(?Send)]
+impl Publisher for QueuePublisher {
+  async fn publish(
+    &self,
+    _routing_key: &str,
+    payload: String,
+    _properties: Properties,
+  ) -> worker::Result<bool> {
+    self.queue.send(&payload).await?;
+
+    Ok(true)
+  }
+}
@@ -0,0 +1,110 @@
+use std::time::Duration;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use worker::{
+  async_trait::async_trait,
+  console_warn,
+  js_sys::{global, Function, Promise, Reflect},
+  wasm_bindgen::{JsCast, JsValue},
+  wasm_bindgen_futures::JsFuture,
+};
+
+use super::Publisher;
+use crate::amqp::Properties;
+
+/// Attempts made to publish a message, including the first.
+pub const MAX_ATTEMPTS: u32 = 3;
+
+/// Ceiling of the delay before the first retry, doubled for each retry after it.
+const BASE_DELAY: Duration = Duration::from_millis(100);
+
+const MAX_DELAY: Duration = Duration::from_secs(2);
+
+/// Retries failed and unrouted publishes of another publisher, with exponential backoff and full
+/// jitter, so that clients retrying together do not all hit the broker at once.
+pub struct RetryPublisher {
+  inner: Box<dyn Publisher>,
+}
+
+impl RetryPublisher {
+  pub fn new(inner: Box<dyn Publisher>) -> Self {
+    Self { inner }
+  }
+}
+
+#[async_trait(?Send)]
+impl Publisher for RetryPublisher {
+  async fn publish(
+    &self,
+    routing_key: &str,
+    payload: String,
+    properties: Properties,
+  ) -> worker::Result<bool> {
+    let mut rng = StdRng::from_entropy();
+
+    let mut attempt = 1;
+    loop {
+      let published = self
+        .inner
+        .publish(routing_key, payload.clone(), properties.clone())
+        .await;
+      if matches!(published, Ok(true)) || attempt >= MAX_ATTEMPTS {
+        return published;
+      }
+
+      match published {
+        Err(err) => console_warn!("Publish attempt {} failed: {}", attempt, err),
+        _ => console_warn!("Publish attempt {} was not routed", attempt),
+      }
+      sleep(backoff(BASE_DELAY, MAX_DELAY, attempt, &mut rng)).await?;
+      attempt += 1;
+    }
+  }
+}
+
+/// Delay before retry number `retry`, counting from 1: a random duration up to `base` doubled for
+/// each earlier retry, capped at `max`.
+pub fn backoff(base: Duration, max: Duration, retry: u32, rng: &mut impl Rng) -> Duration {
+  let ceiling = base
+    .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+    .min(max);
+
+  Duration::from_millis(rng.gen_range(0..=ceiling.as_millis() as u64))
+}
+
+/// Resolves after `delay`, through the runtime's `setTimeout`.
+async fn sleep(delay: Duration) -> worker::Result<()> {
+  let set_timeout: Function = Reflect::get(&global(), &"setTimeout".into())?.dyn_into()?;
+  let millis = JsValue::from_f64(delay.as_millis() as f64);
+
+  let promise = Promise::new(&mut |resolve, reject| {
+    if let Err(err) = set_timeout.call2(&JsValue::NULL, &resolve, &millis) {
+      let _ = reject.call1(&JsValue::NULL, &err);
+    }
+  });
+  JsFuture::from(promise).await?;
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use std::time::Duration;
+
+  use rand::{rngs::StdRng, SeedableRng};
+
+  use super::backoff;
+
+  #[test]
+  fn test_backoff() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let base = Duration::from_millis(100);
+    let max = Duration::from_secs(2);
+
+    for _ in 0..100 {
+      assert!(backoff(base, max, 1, &mut rng) <= base);
+      assert!(backoff(base, max, 3, &mut rng) <= base * 4);
+      assert!(backoff(base, max, 40, &mut rng) <= max);
+    }
+  }
+}
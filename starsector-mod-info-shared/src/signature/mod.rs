@@ -0,0 +1,133 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Header carrying a webhook signature, in the form `t=<unix seconds>,v1=<hex hmac>`.
+///
+/// The HMAC is SHA-256 over `<t>.<body>`. Senders may include several `v1` entries, such as one
+/// per key while keys are being rotated.
+pub const SIGNATURE_HEADER: &str = "X-Signature";
+
+/// Default number of seconds a signature's timestamp may differ from the receiver's clock.
+pub const DEFAULT_TOLERANCE_SECS: i64 = 300;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SignatureError {
+  /// The header is missing its timestamp or signatures.
+  Malformed,
+  /// The timestamp is outside the tolerance, so the request may be a replay.
+  Expired,
+  /// No signature matches any of the keys.
+  Mismatch,
+}
+
+/// Builds the signature header value for `body`, sent at `timestamp`.
+pub fn sign(key: &str, timestamp: i64, body: &str) -> String {
+  format!(
+    "t={},v1={}",
+    timestamp,
+    hex::encode(mac(key, timestamp, body).finalize().into_bytes())
+  )
+}
+
+/// Checks a signature header against `body`, accepting a signature made with any of `keys`.
+pub fn verify<'a>(
+  header: &str,
+  body: &str,
+  keys: impl IntoIterator<Item = &'a str>,
+  now: i64,
+  tolerance_secs: i64,
+) -> Result<(), SignatureError> {
+  let mut timestamp = None;
+  let mut signatures = Vec::new();
+  for (name, value) in header
+    .split(',')
+    .filter_map(|part| part.trim().split_once('='))
+  {
+    match name {
+      "t" => timestamp = value.parse::<i64>().ok(),
+      "v1" => signatures.extend(hex::decode(value).ok()),
+      _ => {}
+    }
+  }
+
+  let Some(timestamp) = timestamp else {
+    return Err(SignatureError::Malformed);
+  };
+  if signatures.is_empty() {
+    return Err(SignatureError::Malformed);
+  }
+  if (now - timestamp).abs() > tolerance_secs {
+    return Err(SignatureError::Expired);
+  }
+
+  for key in keys {
+    for signature in &signatures {
+      // `verify_slice` compares in constant time.
+      if mac(key, timestamp, body).verify_slice(signature).is_ok() {
+        return Ok(());
+      }
+    }
+  }
+
+  Err(SignatureError::Mismatch)
+}
+
+/// Compares two secrets in time that does not depend on where they differ.
+///
+/// Both are first hashed under a fixed key, so the comparison also does not leak their lengths.
+pub fn constant_time_eq(a: &str, b: &str) -> bool {
+  let digest = |value: &str| {
+    let mut mac =
+      HmacSha256::new_from_slice(b"constant_time_eq").expect("HMAC accepts keys of any size");
+    mac.update(value.as_bytes());
+    mac
+  };
+
+  // `verify_slice` compares in constant time.
+  digest(a)
+    .verify_slice(&digest(b).finalize().into_bytes())
+    .is_ok()
+}
+
+fn mac(key: &str, timestamp: i64, body: &str) -> HmacSha256 {
+  let mut mac = HmacSha256::new_from_slice(key.as_bytes()).expect("HMAC accepts keys of any size");
+  mac.update(timestamp.to_string().as_bytes());
+  mac.update(b".");
+  mac.update(body.as_bytes());
+  mac
+}
+
+#[cfg(test)]
+mod test {
+  use super::{constant_time_eq, sign, verify, SignatureError};
+
+  #[test]
+  fn test_verify() {
+    let body = r#"{"mods":[]}"#;
+    let header = sign("old", 1000, body);
+
+    assert_eq!(verify(&header, body, ["new", "old"], 1100, 300), Ok(()));
+    assert_eq!(
+      verify(&header, body, ["new"], 1100, 300),
+      Err(SignatureError::Mismatch)
+    );
+    assert_eq!(
+      verify(&header, "{}", ["old"], 1100, 300),
+      Err(SignatureError::Mismatch)
+    );
+    assert_eq!(
+      verify(&header, body, ["old"], 1400, 300),
+      Err(SignatureError::Expired)
+    );
+    assert_eq!(
+      verify("v1=00", body, ["old"], 1000, 300),
+      Err(SignatureError::Malformed)
+    );
+
+    assert!(constant_time_eq("secret", "secret"));
+    assert!(!constant_time_eq("secret", "secreT"));
+    assert!(!constant_time_eq("secret", "secret2"));
+  }
+}
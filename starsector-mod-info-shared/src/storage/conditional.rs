@@ -0,0 +1,145 @@
+use std::cell::Cell;
+
+use serde::{de::DeserializeOwned, Serialize};
+use worker::{
+  js_sys::{Function, Object, Promise, Reflect},
+  wasm_bindgen::{JsCast, JsValue},
+  wasm_bindgen_futures::JsFuture,
+  Bucket, Env,
+};
+
+use super::{get_json_with_etag, put_json};
+
+/// Number of times an update is retried against a newer version of the object before giving up.
+const MAX_ATTEMPTS: usize = 8;
+
+/// An R2 bucket that supports compare-and-swap writes.
+///
+/// `worker::Bucket` does not expose the `onlyIf` option of `put`, so writes go through the raw
+/// binding instead, while reads still use `worker::Bucket`.
+pub struct ConditionalBucket {
+  bucket: Bucket,
+  raw: JsValue,
+  /// Subrequests the bucket may still make, if it was given a budget.
+  budget: Cell<Option<usize>>,
+}
+
+/// Outcome of [`ConditionalBucket::update_json`].
+pub struct Updated {
+  /// Whether the object existed before the update.
+  pub existed: bool,
+  /// Whether the update wrote anything.
+  pub written: bool,
+}
+
+impl ConditionalBucket {
+  pub fn new(env: &Env, binding: &str) -> worker::Result<Self> {
+    Ok(Self {
+      bucket: env.bucket(binding)?,
+      raw: Reflect::get(env, &JsValue::from_str(binding))?,
+      budget: Cell::new(None),
+    })
+  }
+
+  /// Limits the subrequests made through this bucket's own methods to `budget`, counting every
+  /// attempt of an update. Those beyond it fail without being made. Requests made through
+  /// [`Self::bucket`] are not counted.
+  pub fn with_budget(self, budget: usize) -> Self {
+    self.budget.set(Some(budget));
+    self
+  }
+
+  /// Subrequests left in the budget, or `usize::MAX` without one.
+  pub fn remaining(&self) -> usize {
+    self.budget.get().unwrap_or(usize::MAX)
+  }
+
+  pub fn bucket(&self) -> &Bucket {
+    &self.bucket
+  }
+
+  fn spend(&self) -> worker::Result<()> {
+    match self.budget.get() {
+      Some(0) => Err(worker::Error::RustError(
+        "Subrequest budget exhausted".to_string(),
+      )),
+      Some(budget) => {
+        self.budget.set(Some(budget - 1));
+        Ok(())
+      }
+      None => Ok(()),
+    }
+  }
+
+  /// Writes `body` to `key` only if the stored object still has etag `etag`, or, when `etag` is
+  /// `None`, only if there is no object at `key`. Returns whether the write happened.
+  pub async fn put_if(&self, key: &str, body: String, etag: Option<&str>) -> worker::Result<bool> {
+    self.spend()?;
+
+    let only_if = Object::new();
+    match etag {
+      Some(etag) => Reflect::set(&only_if, &"etagMatches".into(), &etag.into())?,
+      None => Reflect::set(&only_if, &"etagDoesNotMatch".into(), &"*".into())?,
+    };
+
+    let options = Object::new();
+    Reflect::set(&options, &"onlyIf".into(), &only_if)?;
+
+    let put: Function = Reflect::get(&self.raw, &"put".into())?.dyn_into()?;
+    let promise: Promise = put
+      .call3(&self.raw, &key.into(), &body.into(), &options)?
+      .dyn_into()?;
+
+    // R2 resolves with `null` rather than an object when the precondition fails.
+    Ok(!JsFuture::from(promise).await?.is_null())
+  }
+
+  /// Reads the JSON object at `key`, applies `f` to it and writes it back, provided nobody else
+  /// wrote the object in the meantime. On conflict the object is re-read and `f` applied again,
+  /// so `f` may run more than once. `f` returns whether it changed anything worth writing.
+  pub async fn update_json<T>(
+    &self,
+    key: &str,
+    mut f: impl FnMut(&mut T) -> bool,
+  ) -> worker::Result<Updated>
+  where
+    T: Serialize + DeserializeOwned + Default,
+  {
+    for _ in 0..MAX_ATTEMPTS {
+      self.spend()?;
+      let (mut value, etag) = match get_json_with_etag::<T>(&self.bucket, key).await? {
+        Some((value, etag)) => (value, Some(etag)),
+        None => (T::default(), None),
+      };
+      let existed = etag.is_some();
+
+      if !f(&mut value) {
+        return Ok(Updated {
+          existed,
+          written: false,
+        });
+      }
+
+      if self
+        .put_if(key, serde_json::to_string(&value)?, etag.as_deref())
+        .await?
+      {
+        return Ok(Updated {
+          existed,
+          written: true,
+        });
+      }
+    }
+
+    Err(worker::Error::RustError(format!(
+      "Gave up writing {} after {} conflicting attempts",
+      key, MAX_ATTEMPTS
+    )))
+  }
+
+  /// Writes `value` to `key` unconditionally.
+  pub async fn put_json<T: Serialize>(&self, key: &str, value: &T) -> worker::Result<()> {
+    self.spend()?;
+    put_json(&self.bucket, key, value).await
+  }
+}
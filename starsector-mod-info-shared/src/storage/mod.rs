@@ -0,0 +1,125 @@
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Serialize};
+use uuid::Uuid;
+use worker::{Bucket, Objects, Response};
+
+use crate::ParseBody;
+
+mod conditional;
+
+pub use conditional::{ConditionalBucket, Updated};
+
+/// Prefix of objects in `STARSECTOR_MOD_METADATA` that hold derived data rather than a mod's
+/// version map.
+pub const RESERVED_PREFIX: &str = "__";
+
+/// Prefix of payloads `persist` failed to apply, kept for inspection and replay.
+pub const DEAD_LETTER_PREFIX: &str = "__dead_letter/";
+
+/// Prefix of the mods `persist` could not apply within a single invocation's subrequests, kept
+/// for a later one.
+pub const DEFERRED_PREFIX: &str = "__deferred/";
+
+/// Prefix of the records of users, kept from the auth service's lifecycle events.
+pub const USER_PREFIX: &str = "__users/";
+
+/// Workers may only hold six connections open at once.
+pub const MAX_CONCURRENT_REQUESTS: usize = 6;
+
+/// Most objects R2 lists at once.
+pub const MAX_LIST_LIMIT: u32 = 1000;
+
+pub fn is_reserved(key: &str) -> bool {
+  key.starts_with(RESERVED_PREFIX)
+}
+
+/// Whether `id` may be stored as a mod's key.
+///
+/// Ids in the reserved namespace would overwrite derived objects such as the ranking index, and
+/// ids containing a slash would be folded away by listings that use `/` as a delimiter.
+pub fn is_valid_mod_id(id: &str) -> bool {
+  !id.is_empty() && !is_reserved(id) && !id.contains('/')
+}
+
+pub fn dead_letter_key(id: &Uuid) -> String {
+  format!("{}{}", DEAD_LETTER_PREFIX, id)
+}
+
+/// Key of the mods deferred at `deferred_at`. Leads with the time, so listings return the oldest
+/// first.
+pub fn deferred_key(deferred_at: DateTime<Utc>, id: &Uuid) -> String {
+  format!(
+    "{}{:013}-{}",
+    DEFERRED_PREFIX,
+    deferred_at.timestamp_millis(),
+    id
+  )
+}
+
+pub fn user_key(user_id: &str) -> String {
+  format!("{}{}", USER_PREFIX, user_id)
+}
+
+pub async fn get_json<T: DeserializeOwned>(
+  bucket: &Bucket,
+  key: &str,
+) -> worker::Result<Option<T>> {
+  if let Some(body) = bucket.get(key).execute().await? {
+    body.parse().await.map(Some)
+  } else {
+    Ok(None)
+  }
+}
+
+/// Like [`get_json`], but also returns the etag of the object that was read.
+pub async fn get_json_with_etag<T: DeserializeOwned>(
+  bucket: &Bucket,
+  key: &str,
+) -> worker::Result<Option<(T, String)>> {
+  if let Some(body) = bucket.get(key).execute().await? {
+    let etag = body.etag();
+    body.parse().await.map(|value| Some((value, etag)))
+  } else {
+    Ok(None)
+  }
+}
+
+/// Lists a page of up to `limit` objects under `prefix`, continuing from `cursor`, for an admin
+/// route to page through.
+///
+/// Mod ids never contain a slash (see [`is_valid_mod_id`]), so keys below `prefix` that do are
+/// folded into delimited prefixes rather than listed. Answers with a 400 when `limit` is out of
+/// R2's range or `cursor` is not one R2 handed out.
+pub async fn list_page(
+  bucket: &Bucket,
+  prefix: &str,
+  cursor: Option<String>,
+  limit: u32,
+) -> Result<Objects, worker::Result<Response>> {
+  if !(1..=MAX_LIST_LIMIT).contains(&limit) {
+    return Err(Response::error(format!("Invalid limit: {}", limit), 400));
+  }
+
+  let mut list = bucket.list().limit(limit).delimiter("/");
+  if !prefix.is_empty() {
+    list = list.prefix(prefix);
+  }
+  if let Some(cursor) = &cursor {
+    list = list.cursor(cursor.clone());
+  }
+
+  match (list.execute().await, cursor) {
+    (Ok(objects), _) => Ok(objects),
+    // R2 cursors are opaque, so a bad one only shows up as a failed listing.
+    (Err(_), Some(cursor)) => Err(Response::error(format!("Invalid cursor: {}", cursor), 400)),
+    (Err(err), None) => Err(Err(err)),
+  }
+}
+
+pub async fn put_json<T: Serialize>(bucket: &Bucket, key: &str, value: &T) -> worker::Result<()> {
+  bucket
+    .put(key, serde_json::to_string(value)?)
+    .execute()
+    .await
+    .map(|_| ())
+}
@@ -14,7 +14,8 @@ use worker::{
 use crate::{
   assert_method,
   durable::{self, *},
-  route_from_req,
+  message::{Message, ScoreUpdated},
+  publisher, route_from_req,
   worker_result_ext::ResultExt,
   DOProvider, ParseBody, ScoreKey, STARSECTOR_MOD_AUTH,
 };
@@ -201,6 +202,9 @@ impl DurableUser {
     if new > high_score {
       TrustedUser::trusted(self)?.cmp_and_set(new).await?;
       self.set_high_score(new).await?;
+
+      let user_id = self.state.id().to_string();
+      publisher::notify(&self.env, Message::ScoreUpdated(ScoreUpdated::new(user_id, new))).await;
     }
     self.set_score(new).await?;
 
@@ -231,8 +235,8 @@ impl User {
     ctx.env.durable_object(STARSECTOR_MOD_AUTH)
   }
 
-  pub fn new<D>(ctx: RouteContext<D>) -> worker::Result<Self> {
-    let namespace = User::namespace(&ctx)?;
+  pub fn new<D>(ctx: &RouteContext<D>) -> worker::Result<Self> {
+    let namespace = User::namespace(ctx)?;
 
     let id = namespace.unique_id()?;
 
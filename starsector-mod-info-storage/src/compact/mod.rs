@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use futures_util::{stream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use starsector_mod_info_shared::{
+  index::{
+    manifest::{Manifest, ManifestEntry, MANIFEST_KEY},
+    ranking::{RankingIndex, RANKING_KEY},
+    search::{SearchIndex, SEARCH_KEY},
+  },
+  mod_info::{retention::Compaction, Metadata, StoredMod},
+  storage::{get_json, is_reserved, put_json, ConditionalBucket, MAX_CONCURRENT_REQUESTS},
+};
+use worker::Env;
+
+/// Key of the report on the latest compaction run, which also records where the next one resumes.
+const REPORT_KEY: &str = "__compaction/report";
+
+/// Mods compacted per run. Each costs up to two subrequests, and a run has to fit within the 50
+/// the free plan allows alongside the index updates.
+const MODS_PER_RUN: u32 = 12;
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy)]
+struct Counts {
+  mods_scanned: usize,
+  mods_rewritten: usize,
+  #[serde(flatten)]
+  compaction: Compaction,
+}
+
+impl Counts {
+  fn add(&mut self, other: Counts) {
+    self.mods_scanned += other.mods_scanned;
+    self.mods_rewritten += other.mods_rewritten;
+    self.compaction.add(other.compaction);
+  }
+}
+
+/// Outcome of the latest run, and progress through the current pass over the bucket.
+#[derive(Serialize, Deserialize, Default)]
+struct Report {
+  run_started: Option<DateTime<Utc>>,
+  run_finished: Option<DateTime<Utc>>,
+  run: Counts,
+  pass_started: Option<DateTime<Utc>>,
+  /// Counts for the current pass so far.
+  pass: Counts,
+  last_pass_finished: Option<DateTime<Utc>>,
+  /// Counts for the last complete pass.
+  last_pass: Option<Counts>,
+  cursor: Option<String>,
+}
+
+/// Compacts the next page of stored mods and resyncs the derived indexes with them.
+///
+/// Each run picks up where the last left off, so the whole bucket is covered over successive
+/// cron triggers. See [`StoredMod::compact`] for what compacting a mod involves.
+///
+/// The related indexes are neither resynced nor pruned. Stored mods do not record which mods were
+/// installed alongside them, so co-installs cannot be rebuilt from them, and compaction always
+/// keeps a mod's newest version, so no mod an index refers to ever disappears.
+pub async fn compact(env: &Env) -> worker::Result<()> {
+  let bucket = &ConditionalBucket::new(env, "STARSECTOR_MOD_METADATA")?;
+  let now = Utc::now();
+
+  let mut report = get_json::<Report>(bucket.bucket(), REPORT_KEY)
+    .await?
+    .unwrap_or_default();
+  if report.cursor.is_none() {
+    report.pass_started = Some(now);
+    report.pass = Counts::default();
+  }
+  report.run_started = Some(now);
+  let mut run = Counts::default();
+
+  // Mod ids never contain a slash (see `is_valid_mod_id`), so the delimiter folds every reserved
+  // object under its prefix.
+  let mut list = bucket.bucket().list().limit(MODS_PER_RUN).delimiter("/");
+  if let Some(cursor) = report.cursor.take() {
+    list = list.cursor(cursor);
+  }
+  let objects = list.execute().await?;
+  let keys: Vec<String> = objects
+    .objects()
+    .into_iter()
+    .map(|object| object.key())
+    .filter(|key| !is_reserved(key))
+    .collect();
+
+  let results: Vec<Option<(String, HashMap<String, Metadata>, Counts)>> = stream::iter(keys)
+    .map(|key| async move {
+      let mut compaction = Compaction::default();
+      let mut versions = HashMap::new();
+      let updated = bucket
+        .update_json(&key, |stored: &mut StoredMod| {
+          compaction = stored.compact(now);
+          versions = stored.versions.clone();
+          compaction.changed() || stored.is_outdated()
+        })
+        .await?;
+
+      Ok::<_, worker::Error>(updated.existed.then(|| {
+        (
+          key,
+          versions,
+          Counts {
+            mods_scanned: 1,
+            mods_rewritten: updated.written.into(),
+            compaction,
+          },
+        )
+      }))
+    })
+    .buffer_unordered(MAX_CONCURRENT_REQUESTS)
+    .try_collect()
+    .await?;
+
+  let mut compacted = Vec::with_capacity(results.len());
+  for (key, versions, counts) in results.into_iter().flatten() {
+    run.add(counts);
+    compacted.push((key, versions));
+  }
+
+  resync_indexes(bucket, &compacted, now).await?;
+
+  report.cursor = objects.truncated().then(|| objects.cursor()).flatten();
+  report.run = run;
+  report.pass.add(run);
+  report.run_finished = Some(Utc::now());
+  if report.cursor.is_none() {
+    report.last_pass_finished = report.run_finished;
+    report.last_pass = Some(report.pass);
+  }
+
+  put_json(bucket.bucket(), REPORT_KEY, &report).await
+}
+
+/// Brings the manifest, ranking and search indexes in line with the stored `versions` of each
+/// mod. Costs three subrequests, or none when `mods` is empty.
+pub async fn resync_indexes(
+  bucket: &ConditionalBucket,
+  mods: &[(String, HashMap<String, Metadata>)],
+  now: DateTime<Utc>,
+) -> worker::Result<()> {
+  if mods.is_empty() {
+    return Ok(());
+  }
+
+  bucket
+    .update_json(MANIFEST_KEY, |manifest: &mut Manifest| {
+      let mut changed = false;
+      for (id, versions) in mods {
+        if let Some(entry) = ManifestEntry::summarise(versions) {
+          changed |= manifest.set(id, entry);
+        }
+      }
+      changed
+    })
+    .await?;
+
+  bucket
+    .update_json(RANKING_KEY, |ranking: &mut RankingIndex| {
+      for (id, versions) in mods {
+        ranking.resync(id, versions);
+      }
+      ranking.prune(now);
+      true
+    })
+    .await?;
+
+  // Mods stored before the search index existed only become searchable once seen here.
+  bucket
+    .update_json(SEARCH_KEY, |search: &mut SearchIndex| {
+      let mut changed = false;
+      for (id, _) in mods {
+        changed |= search.insert(id, None);
+      }
+      changed
+    })
+    .await?;
+
+  Ok(())
+}
@@ -0,0 +1,158 @@
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use starsector_mod_info_shared::{
+  query_param,
+  storage::{dead_letter_key, get_json, list_page, put_json, DEAD_LETTER_PREFIX},
+};
+use uuid::Uuid;
+use worker::{Bucket, Request, Response, RouteContext};
+
+use crate::{
+  dispatch::{dispatch, has_untracked_submissions},
+  persist::budgeted_bucket,
+};
+
+const DEFAULT_PAGE_SIZE: u32 = 50;
+
+/// A webhook payload `persist` failed to apply.
+#[derive(Serialize, Deserialize)]
+pub struct DeadLetter {
+  pub id: Uuid,
+  pub failed_at: DateTime<Utc>,
+  pub error: String,
+  /// Number of times applying the payload has failed, including replays.
+  pub attempts: u32,
+  /// The webhook body exactly as it was delivered.
+  pub payload: String,
+}
+
+/// Stores a payload that failed to apply, returning the id it can be replayed by.
+pub async fn capture(
+  bucket: &Bucket,
+  payload: String,
+  err: &worker::Error,
+) -> worker::Result<Uuid> {
+  let id = Uuid::new_v4();
+  let letter = DeadLetter {
+    id,
+    failed_at: Utc::now(),
+    error: err.to_string(),
+    attempts: 1,
+    payload,
+  };
+  put_json(bucket, &dead_letter_key(&id), &letter).await?;
+
+  Ok(id)
+}
+
+/// Lists captured dead letters, a page at a time. Use [`inspect`] for the details of each.
+pub async fn list<D>(req: Request, ctx: RouteContext<D>) -> worker::Result<Response> {
+  let url = req.url()?;
+  let cursor: Option<String> = query_param!(&url, "cursor");
+  let limit = query_param!(&url, "limit").unwrap_or(DEFAULT_PAGE_SIZE);
+
+  let bucket = ctx.env.bucket("STARSECTOR_MOD_METADATA")?;
+
+  let objects = match list_page(&bucket, DEAD_LETTER_PREFIX, cursor, limit).await {
+    Ok(objects) => objects,
+    Err(res) => return res,
+  };
+
+  let letters: Vec<_> = objects
+    .objects()
+    .into_iter()
+    .map(|object| {
+      let failed_at = Utc
+        .timestamp_millis_opt(object.uploaded().as_millis() as i64)
+        .single();
+      json!({
+        "id": object.key().trim_start_matches(DEAD_LETTER_PREFIX),
+        "failed_at": failed_at,
+      })
+    })
+    .collect();
+
+  Response::from_json(&json!({
+    "dead_letters": letters,
+    "cursor": objects.truncated().then(|| objects.cursor()).flatten(),
+  }))
+}
+
+pub async fn inspect<D>(_req: Request, ctx: RouteContext<D>) -> worker::Result<Response> {
+  let Some(id) = param_id(&ctx) else {
+    return Response::error("Invalid dead letter id", 400);
+  };
+
+  let bucket = ctx.env.bucket("STARSECTOR_MOD_METADATA")?;
+
+  match get_json::<DeadLetter>(&bucket, &dead_letter_key(&id)).await? {
+    Some(letter) => Response::from_json(&letter),
+    None => Response::error(format!("Could not find dead letter: {}", id), 404),
+  }
+}
+
+/// Applies a dead letter's payload again. It is removed once it applies cleanly, otherwise its
+/// error and attempt count are updated.
+///
+/// Replaying is safe even if the payload was partly applied before it failed, since mods record
+/// the submissions applied to them and skip those. Submissions from before they carried an id are
+/// the exception, so payloads holding any are only replayed with `force=true`.
+pub async fn replay<D>(req: Request, ctx: RouteContext<D>) -> worker::Result<Response> {
+  let Some(id) = param_id(&ctx) else {
+    return Response::error("Invalid dead letter id", 400);
+  };
+  let force = query_param!(&req.url()?, "force").unwrap_or(false);
+
+  // Reading the letter and then deleting or updating it are made outside the budget.
+  let bucket = budgeted_bucket(&ctx.env, 2)?;
+  let key = dead_letter_key(&id);
+
+  let Some(mut letter) = get_json::<DeadLetter>(bucket.bucket(), &key).await? else {
+    return Response::error(format!("Could not find dead letter: {}", id), 404);
+  };
+
+  // A payload that cannot be decoded is replayed anyway, to record the error it now fails with.
+  if !force && has_untracked_submissions(&letter.payload).unwrap_or(false) {
+    return Response::error(
+      "Submissions without an id may be counted twice, pass force=true to replay anyway",
+      409,
+    );
+  }
+
+  let applied = dispatch(&bucket, &letter.payload).await;
+  match applied {
+    Ok(summary) => {
+      bucket.bucket().delete(&key).await?;
+      Response::from_json(&summary)
+    }
+    Err(err) => {
+      letter.failed_at = Utc::now();
+      letter.error = err.to_string();
+      letter.attempts += 1;
+      put_json(bucket.bucket(), &key, &letter).await?;
+
+      Response::error(format!("Replay failed: {}", err), 500)
+    }
+  }
+}
+
+pub async fn discard<D>(_req: Request, ctx: RouteContext<D>) -> worker::Result<Response> {
+  let Some(id) = param_id(&ctx) else {
+    return Response::error("Invalid dead letter id", 400);
+  };
+
+  let bucket = ctx.env.bucket("STARSECTOR_MOD_METADATA")?;
+  let key = dead_letter_key(&id);
+
+  if bucket.head(&key).await?.is_none() {
+    return Response::error(format!("Could not find dead letter: {}", id), 404);
+  }
+  bucket.delete(&key).await?;
+
+  Response::empty()
+}
+
+fn param_id<D>(ctx: &RouteContext<D>) -> Option<Uuid> {
+  ctx.param("id").and_then(|id| id.parse().ok())
+}
@@ -0,0 +1,119 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use starsector_mod_info_shared::{
+  amqp,
+  message::{Envelope, Message, MESSAGE_SCHEMA_VERSION},
+  mod_info::Submission,
+  storage::ConditionalBucket,
+  worker_result_ext::ResultExt,
+};
+
+use crate::{persist, users};
+
+/// A legacy payload, holding either a single bare submission or a batch of them.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Delivery {
+  Batch(Vec<Submission>),
+  Single(Submission),
+}
+
+impl From<Delivery> for Vec<Submission> {
+  fn from(value: Delivery) -> Self {
+    match value {
+      Delivery::Batch(submissions) => submissions,
+      Delivery::Single(submission) => vec![submission],
+    }
+  }
+}
+
+/// How much work a call to [`dispatch`] did.
+#[derive(Serialize, Default)]
+pub struct Summary {
+  messages: usize,
+  #[serde(flatten)]
+  submissions: persist::Summary,
+  users: usize,
+}
+
+/// Decodes every message in a webhook payload and routes each to the handler for its kind.
+///
+/// Fails without applying anything if any message is malformed, of an unknown kind, or published
+/// in a newer format than this worker understands, so that the payload is kept as a dead letter
+/// until the worker is updated.
+pub async fn dispatch(bucket: &ConditionalBucket, payload: &str) -> worker::Result<Summary> {
+  let envelopes = decode(payload)?;
+
+  let mut summary = Summary {
+    messages: envelopes.len(),
+    ..Default::default()
+  };
+
+  let mut submissions = Vec::new();
+  let mut created = Vec::new();
+  let mut scored = Vec::new();
+  for envelope in envelopes {
+    match envelope.message {
+      Message::InstalledMods(submission) => submissions.push(submission),
+      Message::UserCreated(event) => created.push(event),
+      Message::ScoreUpdated(event) => scored.push(event),
+    }
+  }
+
+  // Users are few, so they go first and leave the submissions to defer whatever mods do not fit
+  // in the budget.
+  summary.users = users::apply(bucket, created, scored).await?;
+  if !submissions.is_empty() {
+    summary.submissions = persist::apply(bucket, submissions).await?;
+  }
+
+  Ok(summary)
+}
+
+/// Whether `payload` holds submissions without an id. Applying those again may count them twice,
+/// as nothing records which mods they were already applied to.
+pub fn has_untracked_submissions(payload: &str) -> worker::Result<bool> {
+  Ok(decode(payload)?.iter().any(|envelope| {
+    matches!(&envelope.message, Message::InstalledMods(submission) if submission.id.is_none())
+  }))
+}
+
+fn decode(payload: &str) -> worker::Result<Vec<Envelope>> {
+  let mut envelopes = Vec::new();
+  for message in amqp::decode(payload) {
+    if let Some(schema) = message.properties.schema_version() {
+      if schema > u64::from(MESSAGE_SCHEMA_VERSION) {
+        return Err(worker::Error::RustError(format!(
+          "Message schema {} is newer than the supported {}",
+          schema, MESSAGE_SCHEMA_VERSION
+        )));
+      }
+    }
+    envelopes.extend(envelopes_of(message.json().conv()?)?);
+  }
+
+  Ok(envelopes)
+}
+
+/// Reads the envelopes from a single envelope or a batch of them. Anything else is taken as bare
+/// submissions from before messages had kinds.
+fn envelopes_of(value: Value) -> worker::Result<Vec<Envelope>> {
+  match value {
+    Value::Object(ref object) if object.contains_key("kind") => {
+      Ok(vec![serde_json::from_value(value)?])
+    }
+    Value::Array(values) if values.iter().any(|value| value.get("kind").is_some()) => {
+      let mut envelopes = Vec::new();
+      for value in values {
+        envelopes.extend(envelopes_of(value)?);
+      }
+      Ok(envelopes)
+    }
+    legacy => Ok(
+      Vec::<Submission>::from(serde_json::from_value::<Delivery>(legacy)?)
+        .into_iter()
+        .map(|submission| Envelope::new(Message::InstalledMods(submission)))
+        .collect(),
+    ),
+  }
+}
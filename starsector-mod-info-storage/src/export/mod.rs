@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use futures_util::{stream, StreamExt, TryStreamExt};
+use serde::Serialize;
+use starsector_mod_info_shared::{
+  mod_info::{Metadata, StoredMod},
+  query_param,
+  storage::{get_json, is_reserved, list_page, MAX_CONCURRENT_REQUESTS},
+};
+use worker::{Headers, Request, Response, RouteContext};
+
+/// Prefix of export snapshots in `STARSECTOR_MOD_METADATA`.
+const EXPORT_PREFIX: &str = "__export/";
+
+const DEFAULT_PAGE_SIZE: u32 = 25;
+/// Each mod costs a subrequest, and a page has to fit within the 50 the free plan allows
+/// alongside listing the bucket and writing the snapshot.
+const MAX_PAGE_SIZE: u32 = 40;
+
+const CSV_COLUMNS: [&str; 8] = [
+  "mod_id",
+  "version",
+  "total",
+  "canonical",
+  "first_seen",
+  "last_seen",
+  "aliases",
+  "game_versions",
+];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+  Ndjson,
+  Csv,
+}
+
+impl Format {
+  fn content_type(self) -> &'static str {
+    match self {
+      Format::Ndjson => "application/x-ndjson",
+      Format::Csv => "text/csv",
+    }
+  }
+
+  fn extension(self) -> &'static str {
+    match self {
+      Format::Ndjson => "ndjson",
+      Format::Csv => "csv",
+    }
+  }
+}
+
+/// One version of one mod, as exported.
+#[derive(Serialize)]
+struct Record<'a> {
+  mod_id: &'a str,
+  version: &'a str,
+  total: u32,
+  canonical: bool,
+  first_seen: DateTime<Utc>,
+  last_seen: Option<DateTime<Utc>>,
+  aliases: Vec<&'a str>,
+  game_versions: &'a BTreeMap<String, u32>,
+}
+
+impl<'a> Record<'a> {
+  fn new(mod_id: &'a str, version: &'a str, meta: &'a Metadata) -> Self {
+    Self {
+      mod_id,
+      version,
+      total: meta.total,
+      canonical: meta.canonical,
+      first_seen: meta.first_seen,
+      last_seen: meta.last_seen,
+      aliases: meta.aliases.iter().map(String::as_str).collect(),
+      game_versions: &meta.game_versions,
+    }
+  }
+
+  fn to_csv(&self) -> worker::Result<String> {
+    let fields = [
+      self.mod_id.to_owned(),
+      self.version.to_owned(),
+      self.total.to_string(),
+      self.canonical.to_string(),
+      self.first_seen.to_string(),
+      self
+        .last_seen
+        .map(|seen| seen.to_string())
+        .unwrap_or_default(),
+      self.aliases.join(";"),
+      serde_json::to_string(self.game_versions)?,
+    ];
+
+    Ok(csv_row(fields.iter().map(String::as_str)))
+  }
+}
+
+/// Exports a page of every version of every stored mod, as NDJSON or CSV (`format=csv`).
+///
+/// This is a paginated dump rather than a stream: reading each mod costs a subrequest, so a single
+/// invocation could never cover the whole bucket, and each page is built in memory before it is
+/// sent. Callers fetch the pages in turn and concatenate them. Pages follow the bucket's key
+/// order. When there are more, the response carries an
+/// `X-Export-Cursor` header to pass back as `cursor`. With `snapshot=<name>`, each page is also
+/// written to `__export/<name>/`, keyed by its first mod id, for downloading later.
+pub async fn export<D>(req: Request, ctx: RouteContext<D>) -> worker::Result<Response> {
+  let url = req.url()?;
+  let param = |name: &str| {
+    url
+      .query_pairs()
+      .find_map(|(key, val)| (key == name).then(|| val.to_string()))
+  };
+
+  let format = match param("format").as_deref() {
+    None | Some("ndjson") => Format::Ndjson,
+    Some("csv") => Format::Csv,
+    Some(format) => return Response::error(format!("Unknown format: {}", format), 400),
+  };
+  let limit = query_param!(&url, "limit")
+    .unwrap_or(DEFAULT_PAGE_SIZE)
+    .clamp(1, MAX_PAGE_SIZE);
+  let cursor = param("cursor");
+  let snapshot = param("snapshot").filter(|name| !name.is_empty());
+  if let Some(name) = &snapshot {
+    if name.contains('/') {
+      return Response::error("Snapshot names may not contain a slash", 400);
+    }
+  }
+
+  let bucket = &ctx.env.bucket("STARSECTOR_MOD_METADATA")?;
+
+  let first_page = cursor.is_none();
+  // Reserved objects are folded under their prefixes, so only mods are listed.
+  let objects = match list_page(bucket, "", cursor, limit).await {
+    Ok(objects) => objects,
+    Err(res) => return res,
+  };
+  let keys: Vec<String> = objects
+    .objects()
+    .into_iter()
+    .map(|object| object.key())
+    .filter(|key| !is_reserved(key))
+    .collect();
+
+  let mods: Vec<(String, Option<StoredMod>)> = stream::iter(keys)
+    .map(|key| async move {
+      let stored = get_json::<StoredMod>(bucket, &key).await?;
+      Ok::<_, worker::Error>((key, stored))
+    })
+    .buffered(MAX_CONCURRENT_REQUESTS)
+    .try_collect()
+    .await?;
+
+  let mut body = String::new();
+  if format == Format::Csv && first_page {
+    body.push_str(&csv_row(CSV_COLUMNS));
+  }
+  for (id, stored) in &mods {
+    let Some(stored) = stored else {
+      continue;
+    };
+
+    let mut versions: Vec<(&String, &Metadata)> = stored.versions.iter().collect();
+    versions.sort_unstable_by_key(|(version, _)| *version);
+    for (version, meta) in versions {
+      let record = Record::new(id, version, meta);
+      match format {
+        Format::Ndjson => {
+          body.push_str(&serde_json::to_string(&record)?);
+          body.push('\n');
+        }
+        Format::Csv => body.push_str(&record.to_csv()?),
+      }
+    }
+  }
+
+  if let (Some(name), Some((first, _))) = (&snapshot, mods.first()) {
+    let key = format!("{}{}/{}.{}", EXPORT_PREFIX, name, first, format.extension());
+    bucket.put(key, body.clone()).execute().await?;
+  }
+
+  let mut headers = Headers::new();
+  headers.set("Content-Type", format.content_type())?;
+  if let Some(cursor) = objects.truncated().then(|| objects.cursor()).flatten() {
+    headers.set("X-Export-Cursor", &cursor)?;
+  }
+
+  Ok(Response::ok(body)?.with_headers(headers))
+}
+
+fn csv_row<'a>(fields: impl IntoIterator<Item = &'a str>) -> String {
+  let mut row = fields
+    .into_iter()
+    .map(|field| {
+      if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+      } else {
+        field.to_owned()
+      }
+    })
+    .collect::<Vec<_>>()
+    .join(",");
+  row.push_str("\r\n");
+  row
+}
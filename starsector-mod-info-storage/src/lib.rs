@@ -1,8 +1,19 @@
-use persist::persist;
-use starsector_mod_info_shared::worker_result_ext::ResultResponseExt;
+use compact::compact;
+use export::export;
+use migrate::migrate_schema;
+use persist::{budgeted_bucket, consume, persist, resume};
+use starsector_mod_info_shared::{
+  authorize_admin, verify_signature, worker_result_ext::ResultResponseExt,
+};
 use worker::*;
 
+mod compact;
+mod dead_letter;
+mod dispatch;
+mod export;
+mod migrate;
 mod persist;
+mod users;
 mod utils;
 
 fn log_request(req: &Request) {
@@ -25,18 +36,62 @@ pub async fn main(req: Request, env: Env, _ctx: worker::Context) -> Result<Respo
   // Optionally, use the Router to handle matching endpoints, use ":name" placeholders, or "*name"
   // catch-alls to match on specific patterns. Alternatively, use `Router::with_data(D)` to
   // provide arbitrary data that will be accessible in each route via the `ctx.data()` method.
-  let router = Router::new();
+  let mut router = Router::new();
 
-  let webhook_key = env.secret("WEBHOOK_KEY")?.to_string();
+  // Authenticating the broker by a secret in the path leaks the secret into logs, so this is
+  // only kept around, through `WEBHOOK_LEGACY_PATH`, until the broker signs its requests.
+  let legacy_path = env
+    .var("WEBHOOK_LEGACY_PATH")
+    .map(|legacy| legacy.to_string() == "true")
+    .unwrap_or_default();
+  let legacy_route = if legacy_path {
+    Some(format!(
+      "/persist/{}",
+      env.secret("WEBHOOK_KEY")?.to_string()
+    ))
+  } else {
+    None
+  };
+  if let Some(legacy_route) = &legacy_route {
+    router = router.post_async(legacy_route, |mut req, ctx| async move {
+      let payload = req.text().await?;
+      persist(payload, ctx).await.or_500()
+    });
+  }
 
   // Add as many routes as your Worker needs! Each route will get a `Request` for handling HTTP
   // functionality and a `RouteContext` which you can use to  and get route parameters and
   // Environment bindings like KV Stores, Durable Objects, Secrets, and Variables.
   router
-    .post_async(
-      &format!("/persist/{}", webhook_key),
-      |req, ctx| async move { persist(req, ctx).await.or_500() },
-    )
+    .post_async("/persist", |mut req, ctx| async move {
+      let payload = req.text().await?;
+      verify_signature!(&req, &ctx, &payload);
+      persist(payload, ctx).await.or_500()
+    })
+    .post_async("/migrate/schema", |req, ctx| async move {
+      authorize_admin!(&req, &ctx);
+      migrate_schema(req, ctx).await.or_500()
+    })
+    .get_async("/export", |req, ctx| async move {
+      authorize_admin!(&req, &ctx);
+      export(req, ctx).await.or_500()
+    })
+    .get_async("/dead_letters", |req, ctx| async move {
+      authorize_admin!(&req, &ctx);
+      dead_letter::list(req, ctx).await.or_500()
+    })
+    .get_async("/dead_letters/:id", |req, ctx| async move {
+      authorize_admin!(&req, &ctx);
+      dead_letter::inspect(req, ctx).await.or_500()
+    })
+    .post_async("/dead_letters/:id/replay", |req, ctx| async move {
+      authorize_admin!(&req, &ctx);
+      dead_letter::replay(req, ctx).await.or_500()
+    })
+    .delete_async("/dead_letters/:id", |req, ctx| async move {
+      authorize_admin!(&req, &ctx);
+      dead_letter::discard(req, ctx).await.or_500()
+    })
     .get("/worker-version", |_, ctx| {
       let version = ctx.var("WORKERS_RS_VERSION")?.to_string();
       Response::ok(version)
@@ -44,3 +99,43 @@ pub async fn main(req: Request, env: Env, _ctx: worker::Context) -> Result<Respo
     .run(req, env)
     .await
 }
+
+#[event(scheduled)]
+pub async fn scheduled(_event: ScheduledEvent, env: Env, _ctx: ScheduleContext) {
+  utils::set_panic_hook();
+
+  // Deferred mods take the run's subrequests ahead of compaction, which picks up on a later run.
+  match resume(&env).await {
+    Ok(false) => {}
+    Ok(true) => return,
+    Err(err) => {
+      console_error!("Resuming deferred mods failed: {}", err);
+      return;
+    }
+  }
+
+  if let Err(err) = compact(&env).await {
+    console_error!("Compaction failed: {}", err);
+  }
+}
+
+#[event(queue)]
+pub async fn queue(batch: MessageBatch<String>, env: Env, _ctx: Context) -> Result<()> {
+  utils::set_panic_hook();
+
+  // The batch shares the invocation's subrequests, less one per message kept back for capturing
+  // it as a dead letter.
+  let messages = batch.messages()?;
+  let bucket = budgeted_bucket(&env, messages.len())?;
+
+  // Each message is applied and acknowledged on its own, so one failure neither holds back nor
+  // redelivers the rest of the batch.
+  for message in messages {
+    if let Err(err) = consume(&bucket, message.body.clone()).await {
+      console_error!("Consuming a queued message failed, retrying it: {}", err);
+      message.retry();
+    }
+  }
+
+  Ok(())
+}
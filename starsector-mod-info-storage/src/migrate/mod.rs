@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use serde_json::json;
+use starsector_mod_info_shared::{
+  mod_info::{schema::SCHEMA_VERSION, Metadata, StoredMod},
+  query_param,
+  storage::{is_reserved, list_page, ConditionalBucket},
+};
+use worker::{Request, Response, RouteContext};
+
+use crate::compact::resync_indexes;
+
+const DEFAULT_BATCH_SIZE: u32 = 100;
+
+/// Rewrites stored mods that are still in an older schema as the current [`SCHEMA_VERSION`], and
+/// re-keys those whose version keys were derived by an older `canonical_key`.
+///
+/// Reads already upgrade old objects as they go, so this only saves that work on later reads,
+/// and lets old migrations be dropped once every object is current. Run it again whenever
+/// `canonical_key` changes.
+///
+/// Each page of mods is also backfilled into the manifest, ranking and search indexes, so a full
+/// pass builds any index that was introduced after the mods were stored, without waiting for
+/// compaction to reach them.
+///
+/// Processes one page of the bucket per request; callers should keep passing back the returned
+/// `cursor` until it is `null`. Writes are conditional on the object being unchanged since it was
+/// read, so this is safe to run while the broker is still delivering submissions.
+pub async fn migrate_schema<D>(req: Request, ctx: RouteContext<D>) -> worker::Result<Response> {
+  let url = req.url()?;
+  let cursor: Option<String> = query_param!(&url, "cursor");
+  let limit = query_param!(&url, "limit").unwrap_or(DEFAULT_BATCH_SIZE);
+
+  let bucket = ConditionalBucket::new(&ctx.env, "STARSECTOR_MOD_METADATA")?;
+
+  let objects = match list_page(bucket.bucket(), "", cursor, limit).await {
+    Ok(objects) => objects,
+    Err(res) => return res,
+  };
+
+  let mut scanned = 0;
+  let mut migrated = 0;
+  let mut backfilled: Vec<(String, HashMap<String, Metadata>)> = Vec::new();
+  for object in objects.objects() {
+    let key = object.key();
+    if is_reserved(&key) {
+      continue;
+    }
+    let mut versions = HashMap::new();
+    let updated = bucket
+      .update_json(&key, |stored: &mut StoredMod| {
+        versions = stored.versions.clone();
+        stored.is_outdated()
+      })
+      .await?;
+
+    if updated.existed {
+      scanned += 1;
+      backfilled.push((key, versions));
+    }
+    if updated.written {
+      migrated += 1;
+    }
+  }
+  resync_indexes(&bucket, &backfilled, Utc::now()).await?;
+
+  Response::from_json(&json!({
+    "scanned": scanned,
+    "migrated": migrated,
+    "backfilled": backfilled.len(),
+    "schema": SCHEMA_VERSION,
+    "cursor": objects.truncated().then(|| objects.cursor()).flatten(),
+  }))
+}
@@ -1,37 +1,475 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet};
 
+use chrono::{DateTime, Duration, Utc};
+use futures_util::{stream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 use starsector_mod_info_shared::{
-  mod_info::{Metadata, Mod},
-  ParseBody,
+  index::{
+    manifest::{Manifest, ManifestEntry, MANIFEST_KEY},
+    ranking::{RankingIndex, RANKING_KEY},
+    related::{related_key, related_shard, RelatedShard},
+    search::{SearchIndex, SEARCH_KEY},
+  },
+  mod_info::{retention::APPLIED_RETENTION_DAYS, Metadata, Mod, StoredMod, Submission},
+  storage::{
+    deferred_key, get_json, is_valid_mod_id, ConditionalBucket, DEFERRED_PREFIX,
+    MAX_CONCURRENT_REQUESTS,
+  },
 };
-use worker::{Request, Response, RouteContext};
+use uuid::Uuid;
+use worker::{console_error, console_log, Env, Response, RouteContext};
 
-pub async fn persist<D>(mut req: Request, ctx: RouteContext<D>) -> worker::Result<Response> {
-  let mods = req.json::<Vec<Mod>>().await?;
+use crate::{dead_letter::capture, dispatch::dispatch};
 
-  let bucket = ctx.env.bucket("STARSECTOR_MOD_METADATA")?;
+/// Subrequests an invocation may make on the free plan, unless `SUBREQUEST_BUDGET` says otherwise.
+const DEFAULT_SUBREQUEST_BUDGET: usize = 50;
+/// Subrequests a batch costs whichever mods it holds: a read and a write of each of the ranking,
+/// search and manifest indexes, and a write deferring the mods that do not fit.
+const FIXED_COST: usize = 7;
+/// Subrequests kept spare for updates that lose a race and are retried.
+const CONFLICT_ALLOWANCE: usize = 4;
 
-  for mod_info in mods {
-    let mut map = if let Some(body) = bucket.get(mod_info.id.as_str()).execute().await? {
-      body.parse().await?
-    } else {
-      HashMap::<String, Metadata>::new()
-    };
+/// How much work applying a batch of submissions did.
+#[derive(Serialize, Default)]
+pub struct Summary {
+  submissions: usize,
+  skipped: usize,
+  mods: usize,
+  deferred: usize,
+  objects_written: usize,
+}
 
-    let version = mod_info.version.to_string();
-    if let Some(val) = map.get_mut(&version) {
-      val.total += 1;
-    } else {
-      map.insert(version, Metadata::default());
+/// The mods of a batch that did not fit within an invocation's subrequests, along with their
+/// submissions, kept for [`resume`] to apply.
+#[derive(Serialize, Deserialize)]
+struct Deferred {
+  submissions: Vec<Submission>,
+  /// The mods still to be applied. The submissions' other mods already were.
+  pending: BTreeSet<String>,
+}
+
+/// A mod from a submission, along with the submission's game version and user.
+struct Install<'a> {
+  /// Position of the submission in the batch.
+  submission: usize,
+  submission_id: Option<&'a Uuid>,
+  mod_info: &'a Mod,
+  game_version: Option<&'a str>,
+  user_id: Option<&'a str>,
+}
+
+/// The outcome of applying every install of a single mod.
+struct Persisted<'a> {
+  id: &'a str,
+  /// Whether the mod had not been seen before.
+  unseen: bool,
+  /// Whether the mod was written, rather than found with every submission already applied.
+  written: bool,
+  /// The installs that counted towards the mod's totals, rather than repeating a user's earlier
+  /// submission.
+  counted: Vec<&'a Install<'a>>,
+  /// Positions of the submissions that were already applied to the mod.
+  repeated: BTreeSet<usize>,
+  /// The mod's summary for the manifest, as written.
+  entry: Option<ManifestEntry>,
+}
+
+pub async fn persist<D>(payload: String, ctx: RouteContext<D>) -> worker::Result<Response> {
+  // Capturing a failed payload as a dead letter costs the one subrequest kept back.
+  let bucket = budgeted_bucket(&ctx.env, 1)?;
+
+  let applied = dispatch(&bucket, &payload).await;
+  match applied {
+    Ok(summary) => Response::from_json(&summary),
+    Err(err) => {
+      // Acknowledge the delivery once it is safely captured, rather than have the broker retry
+      // it blindly. If capturing fails too, the error still reaches the broker.
+      let id = capture(bucket.bucket(), payload, &err).await?;
+      console_error!("Persist failed, captured as dead letter {}: {}", id, err);
+
+      Response::from_json(&json!({
+        "dead_letter": id,
+        "error": err.to_string(),
+      }))
+    }
+  }
+}
+
+/// Applies a single message from the Cloudflare Queue, holding a single envelope.
+///
+/// A failure is captured as a dead letter like those of [`persist`]. Only if that fails too is
+/// the error returned, so the queue retries the message.
+pub async fn consume(bucket: &ConditionalBucket, payload: String) -> worker::Result<()> {
+  let applied = dispatch(bucket, &payload).await;
+  if let Err(err) = applied {
+    let id = capture(bucket.bucket(), payload, &err).await?;
+    console_error!("Consume failed, captured as dead letter {}: {}", id, err);
+  }
+
+  Ok(())
+}
+
+/// The metadata bucket, limited to the invocation's subrequest budget less the `reserved` ones
+/// made outside it. The budget is set by the `SUBREQUEST_BUDGET` variable.
+pub fn budgeted_bucket(env: &Env, reserved: usize) -> worker::Result<ConditionalBucket> {
+  let budget = env
+    .var("SUBREQUEST_BUDGET")
+    .ok()
+    .and_then(|budget| budget.to_string().parse().ok())
+    .unwrap_or(DEFAULT_SUBREQUEST_BUDGET);
+
+  Ok(
+    ConditionalBucket::new(env, "STARSECTOR_MOD_METADATA")?
+      .with_budget(budget.saturating_sub(reserved)),
+  )
+}
+
+/// Applies a batch of installed mods submissions to the bucket.
+///
+/// Mods that would take the batch past the bucket's subrequest budget are deferred, for the cron
+/// trigger to [`resume`].
+///
+/// Fails on any storage error. Writes made before a failure are kept, but each mod records the
+/// submissions applied to it in the same write as their installs, so applying the same
+/// submissions again never counts those with an id twice. Index updates lost to the failure are
+/// made up by compaction, except for the related indexes.
+pub async fn apply(
+  bucket: &ConditionalBucket,
+  submissions: Vec<Submission>,
+) -> worker::Result<Summary> {
+  apply_pending(bucket, submissions, None).await
+}
+
+/// Applies the oldest mods deferred by [`apply`], deferring again those that still do not fit.
+/// Returns whether there were any.
+///
+/// Mods that fail to apply are left in place for the next run to try again, which is safe for
+/// the same reasons as applying a redelivered batch.
+pub async fn resume(env: &Env) -> worker::Result<bool> {
+  // Listing, reading and deleting the deferred mods are made outside the budget.
+  let bucket = budgeted_bucket(env, 3)?;
+
+  let listed = bucket
+    .bucket()
+    .list()
+    .prefix(DEFERRED_PREFIX)
+    .limit(1)
+    .execute()
+    .await?;
+  let Some(key) = listed
+    .objects()
+    .into_iter()
+    .next()
+    .map(|object| object.key())
+  else {
+    return Ok(false);
+  };
+
+  if let Some(deferred) = get_json::<Deferred>(bucket.bucket(), &key).await? {
+    let summary = apply_pending(&bucket, deferred.submissions, Some(deferred.pending)).await?;
+    console_log!("Resumed {}: {}", key, serde_json::to_string(&summary)?);
+  }
+  bucket.bucket().delete(&key).await?;
+
+  Ok(true)
+}
+
+/// Like [`apply`], but only applies the mods in `pending`, if given. The submissions' other mods
+/// still count as installed alongside them.
+async fn apply_pending(
+  bucket: &ConditionalBucket,
+  submissions: Vec<Submission>,
+  pending: Option<BTreeSet<String>>,
+) -> worker::Result<Summary> {
+  let now = Utc::now();
+
+  let mut summary = Summary {
+    submissions: submissions.len(),
+    ..Default::default()
+  };
+
+  // Fold the whole batch in memory first, so each object is read and written only once.
+  let mut installs: BTreeMap<&str, Vec<Install>> = BTreeMap::new();
+  let mut submitted_ids: Vec<BTreeSet<&str>> = Vec::with_capacity(submissions.len());
+  for (index, submission) in submissions.iter().enumerate() {
+    let mods: Vec<&Mod> = submission
+      .mods
+      .iter()
+      .filter(|mod_info| is_valid_mod_id(&mod_info.id))
+      .collect();
+
+    for mod_info in &mods {
+      if pending
+        .as_ref()
+        .is_some_and(|pending| !pending.contains(&mod_info.id))
+      {
+        continue;
+      }
+      installs.entry(&mod_info.id).or_default().push(Install {
+        submission: index,
+        submission_id: submission.id.as_ref(),
+        mod_info,
+        game_version: submission.game_version.as_deref(),
+        user_id: submission.user_id.as_deref(),
+      });
+    }
+
+    submitted_ids.push(mods.iter().map(|mod_info| mod_info.id.as_str()).collect());
+  }
+  let deferred = plan(bucket.remaining(), &mut installs);
+  summary.mods = installs.len();
+  summary.deferred = deferred.len();
+
+  let persisted: Vec<Persisted> = stream::iter(&installs)
+    .map(|(id, installs)| persist_mod(bucket, id, installs, now))
+    .buffer_unordered(MAX_CONCURRENT_REQUESTS)
+    .try_collect()
+    .await?;
+  summary.objects_written += persisted
+    .iter()
+    .filter(|persisted| persisted.written)
+    .count();
+  let unseen = persisted.iter().any(|persisted| persisted.unseen);
+
+  // A redelivered submission finds itself already applied to every one of its mods.
+  let mut attempted = vec![0; submissions.len()];
+  for installs in installs.values() {
+    let submissions: BTreeSet<usize> = installs.iter().map(|install| install.submission).collect();
+    for submission in submissions {
+      attempted[submission] += 1;
+    }
+  }
+  let mut repeated = vec![0; submissions.len()];
+  for persisted in &persisted {
+    for &submission in &persisted.repeated {
+      repeated[submission] += 1;
+    }
+  }
+  summary.skipped = attempted
+    .iter()
+    .zip(&repeated)
+    .filter(|(attempted, repeated)| **attempted > 0 && attempted == repeated)
+    .count();
+
+  // Only users counted for a mod for the first time add to its co-installations, so repeat
+  // submissions from one user do not skew them.
+  let mut counted_ids: BTreeMap<usize, BTreeSet<&str>> = BTreeMap::new();
+  for persisted in &persisted {
+    for install in &persisted.counted {
+      counted_ids
+        .entry(install.submission)
+        .or_default()
+        .insert(persisted.id);
+    }
+  }
+  let mut related: BTreeMap<u32, RelatedShard> = BTreeMap::new();
+  for (submission, ids) in counted_ids {
+    for id in ids {
+      related
+        .entry(related_shard(id))
+        .or_default()
+        .entry(id.to_owned())
+        .or_default()
+        .record(id, submitted_ids[submission].iter().copied());
+    }
+  }
+
+  let written: Vec<bool> = stream::iter(related)
+    .map(|(shard, deltas)| async move {
+      bucket
+        .update_json(&related_key(shard), |related: &mut RelatedShard| {
+          for (id, delta) in &deltas {
+            related.entry(id.clone()).or_default().merge(delta.clone());
+          }
+          true
+        })
+        .await
+        .map(|updated| updated.written)
+    })
+    .buffer_unordered(MAX_CONCURRENT_REQUESTS)
+    .try_collect()
+    .await?;
+  summary.objects_written += written.into_iter().filter(|written| *written).count();
+
+  // Like the mods' own totals, the ranking only counts installs by users new to a version, so
+  // compaction resyncing it from them does not undo the counts.
+  if persisted
+    .iter()
+    .any(|persisted| !persisted.counted.is_empty())
+  {
+    let updated = bucket
+      .update_json(RANKING_KEY, |ranking: &mut RankingIndex| {
+        for persisted in &persisted {
+          for install in &persisted.counted {
+            ranking.record(persisted.id, install.game_version, now);
+          }
+        }
+        ranking.prune(now);
+        true
+      })
+      .await?;
+    if updated.written {
+      summary.objects_written += 1;
+    }
+  }
+
+  // Only touch the search index when it could be missing something.
+  let named = installs
+    .values()
+    .flatten()
+    .any(|install| install.mod_info.name.is_some());
+  if unseen || named {
+    let updated = bucket
+      .update_json(SEARCH_KEY, |search: &mut SearchIndex| {
+        let mut changed = false;
+        for Install { mod_info, .. } in installs.values().flatten() {
+          changed |= search.insert(&mod_info.id, mod_info.name.as_deref());
+        }
+        changed
+      })
+      .await?;
+    if updated.written {
+      summary.objects_written += 1;
     }
+  }
 
-    let stringified_map = serde_json::to_string(&map)?;
+  let entries: Vec<(&str, ManifestEntry)> = persisted
+    .into_iter()
+    .filter_map(|persisted| Some((persisted.id, persisted.entry?)))
+    .collect();
+  if !entries.is_empty() {
+    let updated = bucket
+      .update_json(MANIFEST_KEY, |manifest: &mut Manifest| {
+        let mut changed = false;
+        for (id, entry) in &entries {
+          changed |= manifest.update(id, entry.clone());
+        }
+        changed
+      })
+      .await?;
+    if updated.written {
+      summary.objects_written += 1;
+    }
+  }
 
+  if !deferred.is_empty() {
+    let submissions = submissions
+      .into_iter()
+      .filter(|submission| {
+        submission
+          .mods
+          .iter()
+          .any(|mod_info| deferred.contains(&mod_info.id))
+      })
+      .collect();
+    let key = deferred_key(now, &Uuid::new_v4());
     bucket
-      .put(mod_info.id.as_str(), stringified_map)
-      .execute()
+      .put_json(
+        &key,
+        &Deferred {
+          submissions,
+          pending: deferred,
+        },
+      )
       .await?;
+    summary.objects_written += 1;
+  }
+
+  Ok(summary)
+}
+
+/// Drops from `installs` the mods that would take applying them past `budget` subrequests, and
+/// returns their ids.
+///
+/// Each mod costs a read and a write, as does each related shard the mods fall in, on top of the
+/// [`FIXED_COST`]. Mods are taken in order of their shard, so the ones kept share few shards.
+fn plan(budget: usize, installs: &mut BTreeMap<&str, Vec<Install>>) -> BTreeSet<String> {
+  let mut budget = budget.saturating_sub(FIXED_COST + CONFLICT_ALLOWANCE);
+
+  let mut ids: Vec<&str> = installs.keys().copied().collect();
+  ids.sort_by_key(|id| (related_shard(id), *id));
+
+  let mut shards = BTreeSet::new();
+  let mut deferred = BTreeSet::new();
+  for id in ids {
+    let shard = related_shard(id);
+    let cost = if shards.contains(&shard) { 2 } else { 4 };
+    if cost <= budget {
+      budget -= cost;
+      shards.insert(shard);
+    } else {
+      deferred.insert(id.to_owned());
+    }
   }
 
-  Response::ok("OK")
+  installs.retain(|id, _| !deferred.contains(*id));
+  deferred
+}
+
+/// Applies every install of a single mod to its stored version map.
+async fn persist_mod<'a>(
+  bucket: &ConditionalBucket,
+  id: &'a str,
+  installs: &'a [Install<'a>],
+  now: DateTime<Utc>,
+) -> worker::Result<Persisted<'a>> {
+  let mut counted = Vec::new();
+  let mut repeated = BTreeSet::new();
+  let mut entry = None;
+  let updated = bucket
+    .update_json(id, |stored: &mut StoredMod| {
+      // The update is retried from a fresh read whenever it loses a race.
+      counted.clear();
+      repeated.clear();
+
+      // The broker redelivers messages it did not see acknowledged, and a failed payload may be
+      // replayed, so a submission already applied to this mod must not count twice.
+      let mut fresh = Vec::new();
+      for install in installs {
+        match install.submission_id {
+          Some(id) if stored.has_applied(id) => {
+            repeated.insert(install.submission);
+          }
+          _ => fresh.push(install),
+        }
+      }
+      if fresh.is_empty() {
+        entry = ManifestEntry::summarise(&stored.versions);
+        return false;
+      }
+
+      for install in fresh {
+        if let Some(id) = install.submission_id {
+          stored.mark_applied(*id, now);
+        }
+
+        let mod_info = install.mod_info;
+        let raw = mod_info.version.to_string();
+        let version = mod_info.version.key();
+        let meta = stored
+          .versions
+          .entry(version.clone())
+          .or_insert_with(|| Metadata::new(now));
+        if meta.record(install.user_id, install.game_version, now) {
+          counted.push(install);
+        }
+        if raw != version {
+          meta.aliases.insert(raw);
+        }
+      }
+      stored.forget_applied(now - Duration::days(APPLIED_RETENTION_DAYS));
+      entry = ManifestEntry::summarise(&stored.versions);
+      true
+    })
+    .await?;
+
+  Ok(Persisted {
+    id,
+    unseen: !updated.existed,
+    written: updated.written,
+    counted,
+    repeated,
+    entry,
+  })
 }
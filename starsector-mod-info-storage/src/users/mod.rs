@@ -0,0 +1,75 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use futures_util::{stream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use starsector_mod_info_shared::{
+  message::{ScoreUpdated, UserCreated},
+  storage::{user_key, ConditionalBucket, MAX_CONCURRENT_REQUESTS},
+};
+
+/// What the storage worker knows about a user, from the auth service's events.
+#[derive(Serialize, Deserialize, Default)]
+struct UserRecord {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  created_at: Option<DateTime<Utc>>,
+  #[serde(default)]
+  high_score: u32,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  updated_at: Option<DateTime<Utc>>,
+}
+
+/// Applies user lifecycle and score events, returning how many user records were written.
+///
+/// Events are idempotent and may arrive out of order: a record keeps its earliest creation time
+/// and its highest score.
+pub async fn apply(
+  bucket: &ConditionalBucket,
+  created: Vec<UserCreated>,
+  scored: Vec<ScoreUpdated>,
+) -> worker::Result<usize> {
+  let mut events: BTreeMap<String, (Vec<UserCreated>, Vec<ScoreUpdated>)> = BTreeMap::new();
+  for event in created {
+    events
+      .entry(event.user_id.clone())
+      .or_default()
+      .0
+      .push(event);
+  }
+  for event in scored {
+    events
+      .entry(event.user_id.clone())
+      .or_default()
+      .1
+      .push(event);
+  }
+
+  let written: Vec<bool> = stream::iter(&events)
+    .map(|(user_id, (created, scored))| async move {
+      bucket
+        .update_json(&user_key(user_id), |record: &mut UserRecord| {
+          let mut changed = false;
+          for event in created {
+            if record.created_at.map_or(true, |at| event.created_at < at) {
+              record.created_at = Some(event.created_at);
+              changed = true;
+            }
+          }
+          for event in scored {
+            if event.high_score > record.high_score {
+              record.high_score = event.high_score;
+              record.updated_at = Some(event.updated_at);
+              changed = true;
+            }
+          }
+          changed
+        })
+        .await
+        .map(|updated| updated.written)
+    })
+    .buffer_unordered(MAX_CONCURRENT_REQUESTS)
+    .try_collect()
+    .await?;
+
+  Ok(written.into_iter().filter(|written| *written).count())
+}
@@ -1,10 +1,9 @@
-use std::convert::TryInto;
-
-use serde_json::Value;
-use starsector_mod_info_shared::{amqp::HTTPAmqp, mod_info::Mod};
-use worker::{wasm_bindgen::JsValue, Fetch, Headers, Request, RequestInit, Response, RouteContext};
-
-const AMQP_USERNAME: &str = "rbetzayv";
+use starsector_mod_info_shared::{
+  middleware::authentication::user_id,
+  mod_info::Submission,
+  publisher::{self, send_submission},
+};
+use worker::{Request, Response, RouteContext};
 
 pub async fn installed_mods<D>(mut req: Request, ctx: RouteContext<D>) -> worker::Result<Response> {
   if req
@@ -18,7 +17,7 @@ pub async fn installed_mods<D>(mut req: Request, ctx: RouteContext<D>) -> worker
     return Response::error("Invalid User-Agent", 400);
   }
 
-  let json: Vec<Mod> = match req.json().await {
+  let json: Submission = match req.json().await {
     Ok(json) => json,
     Err(err) => {
       return match err {
@@ -28,40 +27,16 @@ pub async fn installed_mods<D>(mut req: Request, ctx: RouteContext<D>) -> worker
     }
   };
 
-  let http_amqp: String = HTTPAmqp::new("write", json)?.try_into()?;
-
-  let key = ctx.secret("AMQP_KEY")?.to_string();
-
-  let mut headers = Headers::new();
-  headers.append("Content-Type", "application/json")?;
-  let credentials = base64::encode(format!("{}:{}", AMQP_USERNAME, key));
-  let base64 = format!("Basic {}", credentials);
-  headers.append("Authorization", &base64)?;
-
-  let amqp_request = Request::new_with_init(
-    &format!(
-      "https://moose.rmq.cloudamqp.com/api/exchanges/{}/amq.default/publish",
-      AMQP_USERNAME
-    ),
-    RequestInit::new()
-      .with_method(worker::Method::Post)
-      .with_headers(headers)
-      .with_body(Some(JsValue::from_str(&http_amqp))),
-  )?;
+  let Some(user_id) = user_id(&req)? else {
+    return Response::error("Authorization header malformed or missing", 400);
+  };
 
-  let routed = Fetch::Request(amqp_request)
-    .send()
-    .await?
-    .json::<Value>()
-    .await?;
+  let submission = json.with_new_id().with_user_id(user_id);
+  let publisher = publisher::reliable_from_env(&ctx.env)?;
 
-  if routed
-    .get("routed")
-    .and_then(|routed| routed.as_bool())
-    .unwrap_or_default()
-  {
+  if send_submission(publisher.as_ref(), submission).await? {
     Response::ok("OK")
   } else {
-    Response::error("Failed to write to RabbitMQ", 502)
+    Response::error("Failed to publish submission", 502)
   }
 }
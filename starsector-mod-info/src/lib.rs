@@ -1,10 +1,22 @@
 use installed_mods::installed_mods;
-use mod_data::req_mod_data_by_get;
-use starsector_mod_info_shared::{authenticate, rate_limit, worker_result_ext::ResultResponseExt};
+use list_mods::list_mods;
+use mod_data::{req_mod_data_by_get, req_mod_data_by_post, req_mod_history};
+use outbox::outbox_depth;
+use related_mods::related_mods;
+use search_mods::search_mods;
+use starsector_mod_info_shared::{
+  authenticate, authorize_admin, rate_limit, worker_result_ext::ResultResponseExt,
+};
+use top_mods::top_mods;
 use worker::*;
 
 mod installed_mods;
+mod list_mods;
 mod mod_data;
+mod outbox;
+mod related_mods;
+mod search_mods;
+mod top_mods;
 mod utils;
 
 fn log_request(req: &Request) {
@@ -41,6 +53,28 @@ pub async fn main(req: Request, env: Env, _ctx: worker::Context) -> Result<Respo
     .get_async("/mod_data", |req, ctx| async move {
       req_mod_data_by_get(req, ctx).await.or_500()
     })
+    .post_async("/mod_data", |req, ctx| async move {
+      req_mod_data_by_post(req, ctx).await.or_500()
+    })
+    .get_async("/mod_data/history", |req, ctx| async move {
+      req_mod_history(req, ctx).await.or_500()
+    })
+    .get_async("/mods", |req, ctx| async move {
+      list_mods(req, ctx).await.or_500()
+    })
+    .get_async("/mods/top", |req, ctx| async move {
+      top_mods(req, ctx).await.or_500()
+    })
+    .get_async("/mods/search", |req, ctx| async move {
+      search_mods(req, ctx).await.or_500()
+    })
+    .get_async("/mods/:id/related", |req, ctx| async move {
+      related_mods(req, ctx).await.or_500()
+    })
+    .get_async("/outbox", |req, ctx| async move {
+      authorize_admin!(&req, &ctx);
+      outbox_depth(req, ctx).await.or_500()
+    })
     .get("/worker_version", |_, ctx| {
       let version = ctx.var("WORKERS_RS_VERSION")?.to_string();
       Response::ok(version)
@@ -0,0 +1,51 @@
+use serde::Serialize;
+use starsector_mod_info_shared::{
+  index::manifest::{is_valid_cursor, Manifest, ManifestEntry, MANIFEST_KEY},
+  query_param,
+  storage::get_json,
+};
+use worker::{Request, Response, RouteContext};
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+#[derive(Serialize)]
+struct ListedMod<'a> {
+  id: &'a str,
+  #[serde(flatten)]
+  entry: &'a ManifestEntry,
+}
+
+#[derive(Serialize)]
+struct ModList<'a> {
+  mods: Vec<ListedMod<'a>>,
+  cursor: Option<String>,
+}
+
+/// Pages through every mod the service knows about, in id order.
+pub async fn list_mods<D>(req: Request, ctx: RouteContext<D>) -> worker::Result<Response> {
+  let url = req.url()?;
+
+  let limit = query_param!(&url, "limit")
+    .unwrap_or(DEFAULT_LIMIT)
+    .clamp(1, MAX_LIMIT);
+  let cursor: Option<String> = query_param!(&url, "cursor");
+  if let Some(cursor) = &cursor {
+    if !is_valid_cursor(cursor) {
+      return Response::error(format!("Invalid cursor: {}", cursor), 400);
+    }
+  }
+
+  let bucket = ctx.env.bucket("STARSECTOR_MOD_METADATA")?;
+  let manifest = get_json::<Manifest>(&bucket, MANIFEST_KEY)
+    .await?
+    .unwrap_or_default();
+
+  let (entries, cursor) = manifest.page(cursor.as_deref(), limit);
+  let mods = entries
+    .into_iter()
+    .map(|(id, entry)| ListedMod { id, entry })
+    .collect();
+
+  Response::from_json(&ModList { mods, cursor })
+}
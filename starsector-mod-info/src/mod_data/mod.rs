@@ -1,7 +1,108 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
-use starsector_mod_info_shared::{mod_info::Metadata, ParseBody};
-use worker::{Request, Response, RouteContext};
+use chrono::{DateTime, Utc};
+use futures_util::{stream, StreamExt, TryStreamExt};
+use serde::Serialize;
+use starsector_mod_info_shared::{
+  cache::{etag, etag_matches},
+  mod_info::{
+    normalize::{canonical_key, game_version_key},
+    version, History, Metadata, Mod, PublicMetadata, StoredMod,
+  },
+  storage::{get_json_with_etag, is_valid_mod_id, MAX_CONCURRENT_REQUESTS},
+};
+use worker::{Bucket, Cache, Headers, Request, Response, RouteContext, Url};
+
+/// Each mod costs a subrequest to read, and a query has to fit within the 50 the free plan allows
+/// alongside reading and writing the edge cache.
+const DEFAULT_QUERY_LIMIT: usize = 45;
+const DEFAULT_MAX_AGE: u32 = 300;
+
+#[derive(Serialize)]
+struct ModData<'a> {
+  game_version: Option<String>,
+  mods: BTreeMap<&'a str, ModVersions>,
+  missing: Vec<&'a str>,
+}
+
+#[derive(Serialize)]
+struct ModVersions {
+  latest: Option<String>,
+  versions: BTreeMap<String, PublicMetadata>,
+}
+
+impl From<HashMap<String, Metadata>> for ModVersions {
+  fn from(dataset: HashMap<String, Metadata>) -> Self {
+    let versions: BTreeMap<String, PublicMetadata> = dataset
+      .into_iter()
+      .map(|(version, meta)| (version, meta.into()))
+      .collect();
+
+    ModVersions {
+      latest: latest(&versions).map(str::to_owned),
+      versions,
+    }
+  }
+}
+
+impl ModVersions {
+  /// Restricts these versions to those that have been submitted from `game_version`.
+  fn on_game_version(mut self, game_version: Option<&str>) -> Self {
+    if let Some(game_version) = game_version {
+      self
+        .versions
+        .retain(|_, meta| meta.game_versions.contains_key(game_version));
+      self.latest = latest(&self.versions).map(str::to_owned);
+    }
+
+    self
+  }
+}
+
+#[derive(Serialize)]
+struct ModSummaries {
+  game_version: Option<String>,
+  mods: BTreeMap<String, ModSummary>,
+  missing: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct ModSummary {
+  installed: Option<VersionStats>,
+  most_popular: Option<VersionStats>,
+  newest: Option<VersionStats>,
+}
+
+#[derive(Serialize)]
+struct VersionStats {
+  version: String,
+  #[serde(flatten)]
+  metadata: PublicMetadata,
+}
+
+impl VersionStats {
+  fn find(dataset: &BTreeMap<String, PublicMetadata>, version: &str) -> Option<Self> {
+    dataset.get(version).map(|metadata| VersionStats {
+      version: version.to_owned(),
+      metadata: metadata.clone(),
+    })
+  }
+}
+
+#[derive(Serialize)]
+struct ModHistory {
+  id: String,
+  versions: BTreeMap<String, VersionHistory>,
+}
+
+#[derive(Serialize)]
+struct VersionHistory {
+  total: u32,
+  first_seen: DateTime<Utc>,
+  last_seen: Option<DateTime<Utc>>,
+  #[serde(flatten)]
+  history: History,
+}
 
 pub async fn req_mod_data_by_get<D>(
   req: Request,
@@ -14,25 +115,286 @@ pub async fn req_mod_data_by_get<D>(
     let ids: HashSet<&str> = ids.split("+").collect();
 
     if ids.len() > 0 {
-      return mod_data(ids, ctx).await;
+      return mod_data(&req, ids, game_version_param(&url), ctx).await;
     };
   }
 
   Response::error("No query included in request", 400)
 }
 
-async fn mod_data<D>(ids: HashSet<&str>, ctx: RouteContext<D>) -> worker::Result<Response> {
-  if ids.len() > 300 {
-    return Response::error("Query too long", 400);
+pub async fn req_mod_data_by_post<D>(
+  mut req: Request,
+  ctx: RouteContext<D>,
+) -> worker::Result<Response> {
+  let mods: Vec<Mod> = match req.json().await {
+    Ok(json) => json,
+    Err(err) => {
+      return match err {
+        worker::Error::SerdeJsonError(_) => Response::error("Malformed request", 400),
+        _ => Err(err),
+      }
+    }
+  };
+
+  if mods.is_empty() {
+    return Response::error("No mods included in request", 400);
+  }
+
+  let limit = query_limit(&ctx);
+  if mods.len() > limit {
+    return query_too_long(limit);
   }
 
   let bucket = ctx.env.bucket("STARSECTOR_MOD_METADATA")?;
 
-  for id in ids {
-    if let Some(body) = bucket.get(id).execute().await? {
-      let dataset: HashMap<String, Metadata> = body.parse().await?;
+  let mut data = ModSummaries {
+    game_version: game_version_param(&req.url()?),
+    mods: BTreeMap::new(),
+    missing: Vec::new(),
+  };
+  let game_version = data.game_version.as_deref();
+
+  let loaded = load_all(&bucket, mods.iter().map(|mod_info| mod_info.id.as_str())).await?;
+  for (mod_info, loaded) in mods.into_iter().zip(loaded) {
+    let Some((dataset, _)) = loaded else {
+      data.missing.push(mod_info.id);
+      continue;
+    };
+
+    let all: ModVersions = dataset.into();
+    let installed = VersionStats::find(&all.versions, &mod_info.version.key())
+      .or_else(|| VersionStats::find(&all.versions, &mod_info.version.to_string()));
+
+    let ModVersions {
+      latest,
+      versions: dataset,
+    } = all.on_game_version(game_version);
+
+    let most_popular = dataset
+      .iter()
+      .max_by_key(|(_, meta)| match game_version {
+        Some(game_version) => meta.game_versions.get(game_version).copied(),
+        None => Some(meta.total),
+      })
+      .and_then(|(version, _)| VersionStats::find(&dataset, version));
+    let newest = latest.and_then(|version| VersionStats::find(&dataset, &version));
+
+    data.mods.insert(
+      mod_info.id,
+      ModSummary {
+        installed,
+        most_popular,
+        newest,
+      },
+    );
+  }
+
+  data.missing.sort_unstable();
+  data.missing.dedup();
+
+  Response::from_json(&data)
+}
+
+pub async fn req_mod_history<D>(req: Request, ctx: RouteContext<D>) -> worker::Result<Response> {
+  let url = req.url()?;
+  let param = |name: &str| {
+    url
+      .query_pairs()
+      .find_map(|(key, val)| (key == name).then(|| val.to_string()))
+  };
+
+  let Some(id) = param("mod") else {
+    return Response::error("No mod included in request", 400);
+  };
+  let version = param("version").map(|version| canonical_key(&version));
+
+  let bucket = ctx.env.bucket("STARSECTOR_MOD_METADATA")?;
+  let Some((dataset, _)) = load(&bucket, &id).await? else {
+    return Response::error(format!("Could not find mod: {}", id), 404);
+  };
+
+  let now = Utc::now();
+  let versions = dataset
+    .into_iter()
+    .filter(|(key, _)| match &version {
+      Some(version) => version == key,
+      None => true,
+    })
+    .map(|(key, mut meta)| {
+      // Versions that have not been submitted recently have not been rolled up by `persist`.
+      meta.history.roll_up(now);
+
+      let history = VersionHistory {
+        total: meta.total,
+        first_seen: meta.first_seen,
+        last_seen: meta.last_seen,
+        history: meta.history,
+      };
+
+      (key, history)
+    })
+    .collect();
+
+  Response::from_json(&ModHistory { id, versions })
+}
+
+async fn mod_data<D>(
+  req: &Request,
+  ids: HashSet<&str>,
+  game_version: Option<String>,
+  ctx: RouteContext<D>,
+) -> worker::Result<Response> {
+  let limit = query_limit(&ctx);
+  if ids.len() > limit {
+    return query_too_long(limit);
+  }
+
+  let mut ids: Vec<&str> = ids.into_iter().collect();
+  ids.sort_unstable();
+
+  // Equivalent queries share a cache entry regardless of the order ids were requested in.
+  let mut cache_key = req.url()?;
+  cache_key.set_fragment(None);
+  cache_key
+    .query_pairs_mut()
+    .clear()
+    .append_pair("mods", &ids.join("+"));
+  if let Some(game_version) = &game_version {
+    cache_key
+      .query_pairs_mut()
+      .append_pair("game_version", game_version);
+  }
+  let cache_key = cache_key.to_string();
+
+  let cache = Cache::default();
+  if let Some(cached) = cache.get(&cache_key, false).await? {
+    return not_modified_or(req, cached);
+  }
+
+  let bucket = ctx.env.bucket("STARSECTOR_MOD_METADATA")?;
+
+  let mut data = ModData {
+    game_version,
+    mods: BTreeMap::new(),
+    missing: Vec::new(),
+  };
+  let mut tags: Vec<String> = Vec::with_capacity(ids.len() * 2 + 1);
+  tags.push(data.game_version.clone().unwrap_or_default());
+
+  let loaded = load_all(&bucket, ids.iter().copied()).await?;
+  for (id, loaded) in ids.into_iter().zip(loaded) {
+    tags.push(id.to_owned());
+    if let Some((dataset, object_etag)) = loaded {
+      tags.push(object_etag);
+      let versions = ModVersions::from(dataset).on_game_version(data.game_version.as_deref());
+      data.mods.insert(id, versions);
+    } else {
+      tags.push(String::new());
+      data.missing.push(id);
     }
   }
 
-  todo!()
+  let mut headers = Headers::new();
+  headers.set("ETag", &etag(tags.iter().map(String::as_str)))?;
+  headers.set(
+    "Cache-Control",
+    &format!("public, max-age={}", max_age(&ctx)),
+  )?;
+
+  let mut response = Response::from_json(&data)?.with_headers(headers);
+  cache.put(&cache_key, response.cloned()?).await?;
+
+  not_modified_or(req, response)
+}
+
+/// Answers with `304 Not Modified` if the client already holds the current version of `response`.
+fn not_modified_or(req: &Request, response: Response) -> worker::Result<Response> {
+  let etag = response.headers().get("ETag")?;
+  let if_none_match = req.headers().get("If-None-Match")?;
+
+  match (etag, if_none_match) {
+    (Some(etag), Some(if_none_match)) if etag_matches(&if_none_match, &etag) => {
+      let mut headers = Headers::new();
+      headers.set("ETag", &etag)?;
+      if let Some(cache_control) = response.headers().get("Cache-Control")? {
+        headers.set("Cache-Control", &cache_control)?;
+      }
+
+      Ok(Response::empty()?.with_status(304).with_headers(headers))
+    }
+    _ => Ok(response),
+  }
+}
+
+fn latest<T>(versions: &BTreeMap<String, T>) -> Option<&str> {
+  versions
+    .keys()
+    .max_by(|a, b| version::compare(a, b))
+    .map(String::as_str)
+}
+
+/// Optional `game_version` query parameter, normalized to the key installs are aggregated under.
+fn game_version_param(url: &Url) -> Option<String> {
+  url
+    .query_pairs()
+    .find_map(|(key, val)| (key == "game_version").then(|| game_version_key(&val)))
+}
+
+async fn load(
+  bucket: &Bucket,
+  id: &str,
+) -> worker::Result<Option<(HashMap<String, Metadata>, String)>> {
+  if !is_valid_mod_id(id) {
+    return Ok(None);
+  }
+
+  // Older schemas are upgraded as they are read, but only `persist` writes them back.
+  Ok(
+    get_json_with_etag::<StoredMod>(bucket, id)
+      .await?
+      .map(|(stored, etag)| (stored.versions, etag)),
+  )
+}
+
+/// Like [`load`] for each of `ids`, in order, reading a few at a time.
+async fn load_all<'a>(
+  bucket: &Bucket,
+  ids: impl Iterator<Item = &'a str>,
+) -> worker::Result<Vec<Option<(HashMap<String, Metadata>, String)>>> {
+  stream::iter(ids)
+    .map(|id| load(bucket, id))
+    .buffered(MAX_CONCURRENT_REQUESTS)
+    .try_collect()
+    .await
+}
+
+/// Maximum number of mods a single query may ask for, configurable through the
+/// `MOD_DATA_QUERY_LIMIT` variable. Raising it past [`DEFAULT_QUERY_LIMIT`] needs a plan that
+/// allows more subrequests per request.
+fn query_limit<D>(ctx: &RouteContext<D>) -> usize {
+  ctx
+    .var("MOD_DATA_QUERY_LIMIT")
+    .ok()
+    .and_then(|limit| limit.to_string().parse().ok())
+    .unwrap_or(DEFAULT_QUERY_LIMIT)
+}
+
+/// Lifetime of cached `/mod_data` responses in seconds, configurable through the
+/// `MOD_DATA_MAX_AGE` variable.
+fn max_age<D>(ctx: &RouteContext<D>) -> u32 {
+  ctx
+    .var("MOD_DATA_MAX_AGE")
+    .ok()
+    .and_then(|max_age| max_age.to_string().parse().ok())
+    .unwrap_or(DEFAULT_MAX_AGE)
+}
+
+fn query_too_long(limit: usize) -> worker::Result<Response> {
+  Response::error(
+    format!(
+      "Query too long: at most {} mods may be requested at once",
+      limit
+    ),
+    400,
+  )
 }
@@ -0,0 +1,9 @@
+use starsector_mod_info_shared::publisher::Outbox;
+use worker::{Request, Response, RouteContext};
+
+/// Reports how many submissions are waiting in the outbox for the broker to take them.
+pub async fn outbox_depth<D>(_req: Request, ctx: RouteContext<D>) -> worker::Result<Response> {
+  let depth = Outbox::from_env(&ctx.env)?.depth().await?;
+
+  Response::from_json(&depth)
+}
@@ -0,0 +1,65 @@
+use serde::Serialize;
+use starsector_mod_info_shared::{
+  index::{
+    ranking::{RankingIndex, RANKING_KEY},
+    related::{related_key, related_shard, RelatedShard},
+  },
+  query_param,
+  storage::get_json,
+};
+use worker::{Request, Response, RouteContext};
+
+const DEFAULT_COUNT: usize = 10;
+const MAX_COUNT: usize = 100;
+
+#[derive(Serialize)]
+struct RelatedMods<'a> {
+  id: &'a str,
+  installs: u64,
+  related: Vec<RelatedMod<'a>>,
+}
+
+#[derive(Serialize)]
+struct RelatedMod<'a> {
+  id: &'a str,
+  co_installs: u64,
+  score: f64,
+}
+
+pub async fn related_mods<D>(req: Request, ctx: RouteContext<D>) -> worker::Result<Response> {
+  let Some(id) = ctx.param("id") else {
+    return Response::error("No mod included in request", 400);
+  };
+
+  let count = query_param!(&req.url()?, "n")
+    .unwrap_or(DEFAULT_COUNT)
+    .min(MAX_COUNT);
+
+  let bucket = ctx.env.bucket("STARSECTOR_MOD_METADATA")?;
+
+  let Some(index) = get_json::<RelatedShard>(&bucket, &related_key(related_shard(id)))
+    .await?
+    .and_then(|mut shard| shard.remove(id))
+  else {
+    return Response::error(format!("Could not find mod: {}", id), 404);
+  };
+  let ranking = get_json::<RankingIndex>(&bucket, RANKING_KEY)
+    .await?
+    .unwrap_or_default();
+
+  let related = index
+    .top(count, &ranking)
+    .into_iter()
+    .map(|(id, co_installs, score)| RelatedMod {
+      id,
+      co_installs,
+      score,
+    })
+    .collect();
+
+  Response::from_json(&RelatedMods {
+    id,
+    installs: index.installs,
+    related,
+  })
+}
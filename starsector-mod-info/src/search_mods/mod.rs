@@ -0,0 +1,43 @@
+use serde::Serialize;
+use starsector_mod_info_shared::{
+  index::search::{is_valid_cursor, Hit, SearchIndex, SEARCH_KEY},
+  query_param,
+  storage::get_json,
+};
+use worker::{Request, Response, RouteContext};
+
+const DEFAULT_LIMIT: usize = 25;
+const MAX_LIMIT: usize = 100;
+
+#[derive(Serialize)]
+struct SearchResults<'a> {
+  mods: Vec<Hit<'a>>,
+  cursor: Option<String>,
+}
+
+pub async fn search_mods<D>(req: Request, ctx: RouteContext<D>) -> worker::Result<Response> {
+  let url = req.url()?;
+
+  let query: Option<String> = query_param!(&url, "q");
+  let Some(query) = query.filter(|query| !query.trim().is_empty()) else {
+    return Response::error("No query included in request", 400);
+  };
+  let limit = query_param!(&url, "limit")
+    .unwrap_or(DEFAULT_LIMIT)
+    .clamp(1, MAX_LIMIT);
+  let cursor: Option<String> = query_param!(&url, "cursor");
+  if let Some(cursor) = &cursor {
+    if !is_valid_cursor(cursor) {
+      return Response::error(format!("Invalid cursor: {}", cursor), 400);
+    }
+  }
+
+  let bucket = ctx.env.bucket("STARSECTOR_MOD_METADATA")?;
+  let index = get_json::<SearchIndex>(&bucket, SEARCH_KEY)
+    .await?
+    .unwrap_or_default();
+
+  let (mods, cursor) = index.search(&query, cursor.as_deref(), limit);
+
+  Response::from_json(&SearchResults { mods, cursor })
+}
@@ -0,0 +1,64 @@
+use chrono::Utc;
+use serde::Serialize;
+use starsector_mod_info_shared::{
+  index::ranking::{RankingIndex, RANKING_KEY, RETENTION_DAYS},
+  mod_info::normalize::game_version_key,
+  query_param,
+  storage::get_json,
+};
+use worker::{Request, Response, RouteContext};
+
+const DEFAULT_COUNT: usize = 25;
+const MAX_COUNT: usize = 100;
+
+#[derive(Serialize)]
+struct TopMods<'a> {
+  game_version: Option<&'a str>,
+  days: Option<i64>,
+  mods: Vec<TopMod<'a>>,
+}
+
+#[derive(Serialize)]
+struct TopMod<'a> {
+  id: &'a str,
+  installs: u64,
+}
+
+pub async fn top_mods<D>(req: Request, ctx: RouteContext<D>) -> worker::Result<Response> {
+  let url = req.url()?;
+
+  let count = query_param!(&url, "n")
+    .unwrap_or(DEFAULT_COUNT)
+    .min(MAX_COUNT);
+  let days: Option<i64> = query_param!(&url, "days");
+  // Installs are counted under normalized game versions, so `0.96a-RC10` finds those of `0.96a`.
+  let game_version =
+    query_param!(&url, "game_version").map(|game_version: String| game_version_key(&game_version));
+
+  if let Some(days) = days {
+    if !(1..=RETENTION_DAYS).contains(&days) {
+      return Response::error(
+        format!("days must be between 1 and {}", RETENTION_DAYS),
+        400,
+      );
+    }
+  }
+
+  let bucket = ctx.env.bucket("STARSECTOR_MOD_METADATA")?;
+  let ranking = get_json::<RankingIndex>(&bucket, RANKING_KEY)
+    .await?
+    .unwrap_or_default();
+
+  let game_version = game_version.as_deref();
+  let mods = ranking
+    .top(count, game_version, days, Utc::now())
+    .into_iter()
+    .map(|(id, installs)| TopMod { id, installs })
+    .collect();
+
+  Response::from_json(&TopMods {
+    game_version,
+    days,
+    mods,
+  })
+}